@@ -0,0 +1,367 @@
+// Arithmetic and logic operations of the CPU
+// Every operation computes its flags from the operands and returns them in the F register layout
+use crate::cpu::Flags;
+use crate::instruction::ShiftOp;
+
+fn carry_value(flags: u8) -> u8 {
+    if flags & Flags::C as u8 != 0 { 1 } else { 0 }
+}
+
+fn zero_flag(value: u8) -> u8 {
+    if value == 0 { Flags::Z as u8 } else { 0 }
+}
+
+pub fn add(a: u8, n: u8) -> (u8, u8) {
+    adc(a, n, 0)
+}
+
+pub fn adc(a: u8, n: u8, flags: u8) -> (u8, u8) {
+    let carry: u8 = carry_value(flags);
+    let result: u16 = a as u16 + n as u16 + carry as u16;
+    let new_value: u8 = result as u8;
+
+    let mut new_flags: u8 = zero_flag(new_value);
+
+    if (a & 0b00001111) + (n & 0b00001111) + carry > 0b00001111 {
+        new_flags |= Flags::H as u8
+    }
+
+    if result > 0b11111111 {
+        new_flags |= Flags::C as u8
+    }
+
+    (new_value, new_flags)
+}
+
+pub fn sub(a: u8, n: u8) -> (u8, u8) {
+    sbc(a, n, 0)
+}
+
+pub fn sbc(a: u8, n: u8, flags: u8) -> (u8, u8) {
+    let carry: u8 = carry_value(flags);
+    let new_value: u8 = a.wrapping_sub(n).wrapping_sub(carry);
+
+    let mut new_flags: u8 = zero_flag(new_value) | Flags::N as u8;
+
+    if (a & 0b00001111) < (n & 0b00001111) + carry {
+        new_flags |= Flags::H as u8
+    }
+
+    if (a as u16) < n as u16 + carry as u16 {
+        new_flags |= Flags::C as u8
+    }
+
+    (new_value, new_flags)
+}
+
+pub fn and(a: u8, n: u8) -> (u8, u8) {
+    let new_value: u8 = a & n;
+    (new_value, zero_flag(new_value) | Flags::H as u8)
+}
+
+pub fn xor(a: u8, n: u8) -> (u8, u8) {
+    let new_value: u8 = a ^ n;
+    (new_value, zero_flag(new_value))
+}
+
+pub fn or(a: u8, n: u8) -> (u8, u8) {
+    let new_value: u8 = a | n;
+    (new_value, zero_flag(new_value))
+}
+
+// cp only updates the flags, a is left untouched
+pub fn cp(a: u8, n: u8) -> u8 {
+    let (_, new_flags) = sub(a, n);
+    new_flags
+}
+
+// inc and dec keep the carry flag
+pub fn inc(value: u8, flags: u8) -> (u8, u8) {
+    let new_value: u8 = value.wrapping_add(1);
+
+    let mut new_flags: u8 = zero_flag(new_value) | (flags & Flags::C as u8);
+
+    if value & 0b00001111 == 0b00001111 {
+        new_flags |= Flags::H as u8
+    }
+
+    (new_value, new_flags)
+}
+
+pub fn dec(value: u8, flags: u8) -> (u8, u8) {
+    let new_value: u8 = value.wrapping_sub(1);
+
+    let mut new_flags: u8 = zero_flag(new_value) | Flags::N as u8 | (flags & Flags::C as u8);
+
+    if value & 0b00001111 == 0 {
+        new_flags |= Flags::H as u8
+    }
+
+    (new_value, new_flags)
+}
+
+// Adjusts a to a valid binary coded decimal after an addition or a subtraction
+pub fn daa(a: u8, flags: u8) -> (u8, u8) {
+    let mut adjustment: u8 = 0;
+    let mut new_flags: u8 = flags & Flags::N as u8;
+
+    if flags & Flags::N as u8 != 0 {
+        if flags & Flags::H as u8 != 0 {
+            adjustment |= 0x06
+        }
+        if flags & Flags::C as u8 != 0 {
+            adjustment |= 0x60;
+            new_flags |= Flags::C as u8
+        }
+        let new_value: u8 = a.wrapping_sub(adjustment);
+        (new_value, new_flags | zero_flag(new_value))
+    } else {
+        if flags & Flags::H as u8 != 0 || a & 0b00001111 > 0x09 {
+            adjustment |= 0x06
+        }
+        if flags & Flags::C as u8 != 0 || a > 0x99 {
+            adjustment |= 0x60;
+            new_flags |= Flags::C as u8
+        }
+        let new_value: u8 = a.wrapping_add(adjustment);
+        (new_value, new_flags | zero_flag(new_value))
+    }
+}
+
+// add hl, r16 keeps the zero flag and carries from bits 11 and 15
+pub fn add16(hl: u16, value: u16, flags: u8) -> (u16, u8) {
+    let (new_value, carry): (u16, bool) = hl.overflowing_add(value);
+
+    let mut new_flags: u8 = flags & Flags::Z as u8;
+
+    if (hl & 0b0000111111111111) + (value & 0b0000111111111111) > 0b0000111111111111 {
+        new_flags |= Flags::H as u8
+    }
+
+    if carry {
+        new_flags |= Flags::C as u8
+    }
+
+    (new_value, new_flags)
+}
+
+// add sp, e8 and ld hl, sp + e8 compute the carries on the unsigned low byte
+pub fn add_sp_e8(sp: u16, e: u8) -> (u16, u8) {
+    let mut new_flags: u8 = 0b00000000;
+
+    if (sp & 0b0000000000001111) + (e as u16 & 0b0000000000001111) > 0b0000000000001111 {
+        new_flags |= Flags::H as u8
+    }
+
+    if (sp & 0b0000000011111111) + e as u16 > 0b0000000011111111 {
+        new_flags |= Flags::C as u8
+    }
+
+    (sp.wrapping_add_signed(e as i8 as i16), new_flags)
+}
+
+// Rotates and shifts of the $CB prefix, the carry flag receives the bit shifted out
+pub fn shift(op: ShiftOp, value: u8, flags: u8) -> (u8, u8) {
+    let old_carry: u8 = carry_value(flags);
+    let (new_value, carry): (u8, bool) = match op {
+        ShiftOp::Rlc => (value.rotate_left(1), value & 0b10000000 != 0),
+        ShiftOp::Rrc => (value.rotate_right(1), value & 0b00000001 != 0),
+        ShiftOp::Rl => ((value << 1) | old_carry, value & 0b10000000 != 0),
+        ShiftOp::Rr => ((value >> 1) | (old_carry << 7), value & 0b00000001 != 0),
+        ShiftOp::Sla => (value << 1, value & 0b10000000 != 0),
+        ShiftOp::Sra => ((value >> 1) | (value & 0b10000000), value & 0b00000001 != 0),
+        ShiftOp::Swap => (value.rotate_left(4), false),
+        ShiftOp::Srl => (value >> 1, value & 0b00000001 != 0),
+    };
+
+    let mut new_flags: u8 = zero_flag(new_value);
+
+    if carry {
+        new_flags |= Flags::C as u8
+    }
+
+    (new_value, new_flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Z: u8 = Flags::Z as u8;
+    const N: u8 = Flags::N as u8;
+    const H: u8 = Flags::H as u8;
+    const C: u8 = Flags::C as u8;
+
+    fn flags_from(z: bool, n: bool, h: bool, c: bool) -> u8 {
+        (if z { Z } else { 0 })
+            | (if n { N } else { 0 })
+            | (if h { H } else { 0 })
+            | (if c { C } else { 0 })
+    }
+
+    #[test]
+    fn adc_matches_reference_for_all_operands() {
+        for a in 0..=255u8 {
+            for n in 0..=255u8 {
+                for carry in 0..=1u8 {
+                    let flags: u8 = if carry == 1 { C } else { 0 };
+                    let sum: u16 = a as u16 + n as u16 + carry as u16;
+                    let expected_flags: u8 = flags_from(
+                        sum as u8 == 0,
+                        false,
+                        (a & 0xF) + (n & 0xF) + carry > 0xF,
+                        sum > 0xFF,
+                    );
+                    assert_eq!(
+                        adc(a, n, flags),
+                        (sum as u8, expected_flags),
+                        "adc {a} {n} {carry}"
+                    );
+                }
+                assert_eq!(add(a, n), adc(a, n, 0), "add {a} {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn sbc_matches_reference_for_all_operands() {
+        for a in 0..=255u8 {
+            for n in 0..=255u8 {
+                for carry in 0..=1u8 {
+                    let flags: u8 = if carry == 1 { C } else { 0 };
+                    let difference: i16 = a as i16 - n as i16 - carry as i16;
+                    let expected_flags: u8 = flags_from(
+                        difference as u8 == 0,
+                        true,
+                        (a & 0xF) as i16 - (n & 0xF) as i16 - (carry as i16) < 0,
+                        difference < 0,
+                    );
+                    assert_eq!(
+                        sbc(a, n, flags),
+                        (difference as u8, expected_flags),
+                        "sbc {a} {n} {carry}"
+                    );
+                }
+                assert_eq!(sub(a, n), sbc(a, n, 0), "sub {a} {n}");
+                assert_eq!(cp(a, n), sub(a, n).1, "cp {a} {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn logical_operations_match_reference_for_all_operands() {
+        for a in 0..=255u8 {
+            for n in 0..=255u8 {
+                assert_eq!(
+                    and(a, n),
+                    (a & n, flags_from(a & n == 0, false, true, false))
+                );
+                assert_eq!(
+                    xor(a, n),
+                    (a ^ n, flags_from(a ^ n == 0, false, false, false))
+                );
+                assert_eq!(
+                    or(a, n),
+                    (a | n, flags_from(a | n == 0, false, false, false))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inc_and_dec_keep_carry_for_all_operands() {
+        for value in 0..=255u8 {
+            for carry in [0, C] {
+                let incremented: u8 = value.wrapping_add(1);
+                assert_eq!(
+                    inc(value, carry | Z | N | H),
+                    (
+                        incremented,
+                        flags_from(incremented == 0, false, value & 0xF == 0xF, false) | carry
+                    )
+                );
+
+                let decremented: u8 = value.wrapping_sub(1);
+                assert_eq!(
+                    dec(value, carry | Z | H),
+                    (
+                        decremented,
+                        flags_from(decremented == 0, true, value & 0xF == 0, false) | carry
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn daa_corrects_every_bcd_addition_and_subtraction() {
+        for x in 0..100u8 {
+            for y in 0..100u8 {
+                let bcd = |v: u8| ((v / 10) << 4) | (v % 10);
+
+                let (sum, sum_flags) = add(bcd(x), bcd(y));
+                let (adjusted_sum, adjusted_sum_flags) = daa(sum, sum_flags);
+                let expected_sum: u8 = (x + y) % 100;
+                assert_eq!(adjusted_sum, bcd(expected_sum), "daa {x} + {y}");
+                assert_eq!(
+                    adjusted_sum_flags,
+                    flags_from(expected_sum == 0, false, false, x + y >= 100)
+                );
+
+                let (difference, difference_flags) = sub(bcd(x), bcd(y));
+                let (adjusted_difference, adjusted_difference_flags) =
+                    daa(difference, difference_flags);
+                let expected_difference: u8 = (x + 100 - y) % 100;
+                assert_eq!(
+                    adjusted_difference,
+                    bcd(expected_difference),
+                    "daa {x} - {y}"
+                );
+                assert_eq!(
+                    adjusted_difference_flags,
+                    flags_from(expected_difference == 0, true, false, x < y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn add16_carries_from_bits_11_and_15() {
+        assert_eq!(add16(0x0FFF, 0x0001, Z), (0x1000, Z | H));
+        assert_eq!(add16(0xFFFF, 0x0001, 0), (0x0000, H | C));
+        assert_eq!(add16(0x8000, 0x8000, N), (0x0000, C));
+        assert_eq!(add16(0x1234, 0x0101, C), (0x1335, 0));
+    }
+
+    #[test]
+    fn add_sp_e8_matches_reference_for_all_offsets() {
+        for sp in [0x0000u16, 0x00FF, 0x0F0F, 0xFFF8, 0xFFFF, 0x1234] {
+            for e in 0..=255u8 {
+                let expected_flags: u8 = flags_from(
+                    false,
+                    false,
+                    (sp & 0xF) + (e as u16 & 0xF) > 0xF,
+                    (sp & 0xFF) + e as u16 > 0xFF,
+                );
+                assert_eq!(
+                    add_sp_e8(sp, e),
+                    (sp.wrapping_add(e as i8 as u16), expected_flags)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shift_moves_the_shifted_out_bit_into_carry() {
+        assert_eq!(shift(ShiftOp::Rlc, 0b10000001, 0), (0b00000011, C));
+        assert_eq!(shift(ShiftOp::Rrc, 0b00000001, 0), (0b10000000, C));
+        assert_eq!(shift(ShiftOp::Rl, 0b10000000, 0), (0b00000000, Z | C));
+        assert_eq!(shift(ShiftOp::Rl, 0b00000000, C), (0b00000001, 0));
+        assert_eq!(shift(ShiftOp::Rr, 0b00000001, C), (0b10000000, C));
+        assert_eq!(shift(ShiftOp::Sla, 0b11000000, 0), (0b10000000, C));
+        assert_eq!(shift(ShiftOp::Sra, 0b10000001, 0), (0b11000000, C));
+        assert_eq!(shift(ShiftOp::Swap, 0b11110000, C), (0b00001111, 0));
+        assert_eq!(shift(ShiftOp::Swap, 0b00000000, 0), (0b00000000, Z));
+        assert_eq!(shift(ShiftOp::Srl, 0b10000001, 0), (0b01000000, C));
+    }
+}
@@ -0,0 +1,100 @@
+// Boot ROM mapping is based on https://gbdev.io/pandocs/Power_Up_Sequence.html
+use std::fs;
+use std::path::Path;
+
+use crate::error::EmulatorError;
+
+pub const DMG_BOOT_ROM_SIZE: usize = 0x0100;
+pub const CGB_BOOT_ROM_SIZE: usize = 0x0900;
+
+#[derive(Debug)]
+pub struct BootRom {
+    pub data: Vec<u8>,
+    pub is_mapped: bool,
+}
+
+impl BootRom {
+    pub const BOOT_ROM_DISABLE_ADDRESS: u16 = 0xFF50;
+
+    pub fn new(data: Vec<u8>) -> Result<BootRom, EmulatorError> {
+        match data.len() {
+            DMG_BOOT_ROM_SIZE | CGB_BOOT_ROM_SIZE => Ok(BootRom {
+                data,
+                is_mapped: true,
+            }),
+            size => Err(EmulatorError::InvalidBootRomSize { size }),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<BootRom, EmulatorError> {
+        let data: Vec<u8> = fs::read(path).map_err(|error| EmulatorError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        BootRom::new(data)
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.data.len() == CGB_BOOT_ROM_SIZE
+    }
+
+    // Returns the boot ROM byte overlaying memory_address, if any
+    // The CGB boot ROM leaves 0x0100-0x01FF to the cartridge header
+    pub fn get_value_at_memory_address(&self, memory_address: u16) -> Option<u8> {
+        if !self.is_mapped {
+            return None;
+        }
+
+        match memory_address {
+            0x0000..=0x00FF => Some(self.data[memory_address as usize]),
+            0x0200..=0x08FF if self.is_cgb() => Some(self.data[memory_address as usize]),
+            _ => None,
+        }
+    }
+
+    // Any non zero write to 0xFF50 unmaps the boot ROM until the next reset
+    pub fn write_boot_rom_disable(&mut self, value: u8) {
+        if value != 0 {
+            self.is_mapped = false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn rejects_boot_roms_of_unknown_size() {
+        assert_eq!(
+            BootRom::new(vec![0; 0x200]).unwrap_err(),
+            EmulatorError::InvalidBootRomSize { size: 0x200 }
+        );
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_visible() {
+        let mut boot_rom: BootRom = BootRom::new(vec![0xAA; CGB_BOOT_ROM_SIZE]).unwrap();
+        assert_eq!(boot_rom.get_value_at_memory_address(0x00FF), Some(0xAA));
+        assert_eq!(boot_rom.get_value_at_memory_address(0x0134), None);
+        assert_eq!(boot_rom.get_value_at_memory_address(0x0200), Some(0xAA));
+        assert_eq!(boot_rom.get_value_at_memory_address(0x0900), None);
+
+        boot_rom.write_boot_rom_disable(0x00);
+        assert!(boot_rom.is_mapped);
+        boot_rom.write_boot_rom_disable(0x11);
+        assert_eq!(boot_rom.get_value_at_memory_address(0x0000), None);
+    }
+
+    #[test]
+    fn writing_ff50_reveals_the_cartridge() {
+        let mut memory: Memory = Memory::default();
+        memory.load_program(0x0000, &[0x11]);
+        memory.boot_rom = Some(BootRom::new(vec![0x22; DMG_BOOT_ROM_SIZE]).unwrap());
+        assert_eq!(memory.get_value_at_memory_address(0x0000), 0x22);
+
+        memory.set_value_at_memory_address(BootRom::BOOT_ROM_DISABLE_ADDRESS, 0x01);
+        assert_eq!(memory.get_value_at_memory_address(0x0000), 0x11);
+    }
+}
@@ -0,0 +1,107 @@
+// Everything the CPU sees through its address and data pins
+use std::fmt;
+
+use crate::interrupts::{Interrupt, InterruptController};
+use crate::memory::Memory;
+
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    // Called with the CPU M-cycle count each time it advances so the other components can run
+    // alongside the CPU, once per M-cycle unless the CPU is halted
+    fn tick(&mut self, _cycle_counter: u64) {}
+
+    // M-cycles a halted CPU can wait at once, nothing it could notice happening in between
+    fn get_idle_m_cycles(&mut self, _cycle_counter: u64) -> u64 {
+        1
+    }
+
+    // Interrupts both enabled and requested, regardless of IME
+    fn pending_interrupts(&mut self) -> u8 {
+        self.read(InterruptController::INTERRUPT_ENABLE_ADDRESS)
+            & self.read(InterruptController::INTERRUPT_FLAG_ADDRESS)
+            & 0b00011111
+    }
+
+    // Whether a component may still request an enabled interrupt on its own, waking a halted CPU
+    fn may_request_interrupt(&mut self) -> bool {
+        false
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        let interrupt_flag: u8 = self.read(InterruptController::INTERRUPT_FLAG_ADDRESS);
+        self.write(
+            InterruptController::INTERRUPT_FLAG_ADDRESS,
+            interrupt_flag & !interrupt.bit(),
+        );
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.get_value_at_memory_address(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.set_value_at_memory_address(address, value)
+    }
+
+    fn tick(&mut self, cycle_counter: u64) {
+        self.advance_to(cycle_counter)
+    }
+
+    fn get_idle_m_cycles(&mut self, cycle_counter: u64) -> u64 {
+        self.scheduler
+            .get_m_cycles_until_next_event(cycle_counter)
+            .map_or(1, |m_cycles| m_cycles.max(1))
+    }
+
+    fn pending_interrupts(&mut self) -> u8 {
+        self.interrupts.pending()
+    }
+
+    fn may_request_interrupt(&mut self) -> bool {
+        self.timer.is_enabled() && self.interrupts.interrupt_enable & Interrupt::Timer.bit() != 0
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupts.acknowledge(interrupt)
+    }
+}
+
+// 64 KiB of plain RAM without any I/O behaviour, as expected by CPU test vectors
+pub struct FlatBus {
+    pub memory: Box<[u8; 0x10000]>,
+    pub ticks: u64,
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self {
+            memory: Box::new([0; 0x10000]),
+            ticks: 0,
+        }
+    }
+}
+
+impl fmt::Debug for FlatBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FlatBus after {} M-cycles", self.ticks)
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value
+    }
+
+    fn tick(&mut self, _cycle_counter: u64) {
+        self.ticks += 1
+    }
+}
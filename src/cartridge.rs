@@ -0,0 +1,545 @@
+// Cartridge header layout is based on https://gbdev.io/pandocs/The_Cartridge_Header.html
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::error::EmulatorError;
+use crate::mapper::{self, mmm01};
+use crate::memory::ROM_BANK_SIZE;
+
+pub const HEADER_END_ADDRESS: usize = 0x0150;
+
+// Compared by the boot ROM with 0x0104-0x0133
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperType {
+    None,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+    // Unlicensed mappers, detected from the ROM rather than the header
+    WisdomTree,
+    M161,
+    SachenMmc1,
+    SachenMmc2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: MapperType,
+    pub has_ram: bool,
+    pub has_battery: bool,
+    pub has_timer: bool,
+    pub has_rumble: bool,
+    pub has_sensor: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<CartridgeType> {
+        // (mapper, ram, battery, timer, rumble, sensor)
+        let (mapper, has_ram, has_battery, has_timer, has_rumble, has_sensor): (
+            MapperType,
+            bool,
+            bool,
+            bool,
+            bool,
+            bool,
+        ) = match code {
+            0x00 => (MapperType::None, false, false, false, false, false),
+            0x01 => (MapperType::Mbc1, false, false, false, false, false),
+            0x02 => (MapperType::Mbc1, true, false, false, false, false),
+            0x03 => (MapperType::Mbc1, true, true, false, false, false),
+            // MBC2 RAM is built into the mapper
+            0x05 => (MapperType::Mbc2, true, false, false, false, false),
+            0x06 => (MapperType::Mbc2, true, true, false, false, false),
+            0x08 => (MapperType::None, true, false, false, false, false),
+            0x09 => (MapperType::None, true, true, false, false, false),
+            0x0B => (MapperType::Mmm01, false, false, false, false, false),
+            0x0C => (MapperType::Mmm01, true, false, false, false, false),
+            0x0D => (MapperType::Mmm01, true, true, false, false, false),
+            0x0F => (MapperType::Mbc3, false, true, true, false, false),
+            0x10 => (MapperType::Mbc3, true, true, true, false, false),
+            0x11 => (MapperType::Mbc3, false, false, false, false, false),
+            0x12 => (MapperType::Mbc3, true, false, false, false, false),
+            0x13 => (MapperType::Mbc3, true, true, false, false, false),
+            0x19 => (MapperType::Mbc5, false, false, false, false, false),
+            0x1A => (MapperType::Mbc5, true, false, false, false, false),
+            0x1B => (MapperType::Mbc5, true, true, false, false, false),
+            0x1C => (MapperType::Mbc5, false, false, false, true, false),
+            0x1D => (MapperType::Mbc5, true, false, false, true, false),
+            0x1E => (MapperType::Mbc5, true, true, false, true, false),
+            0x20 => (MapperType::Mbc6, true, true, false, false, false),
+            // MBC7 stores its data in an EEPROM rather than RAM
+            0x22 => (MapperType::Mbc7, true, true, false, true, true),
+            0xFC => (MapperType::PocketCamera, true, true, false, false, false),
+            0xFD => (MapperType::Tama5, true, true, true, false, false),
+            0xFE => (MapperType::HuC3, true, true, true, false, false),
+            0xFF => (MapperType::HuC1, true, true, false, false, false),
+            _ => return None,
+        };
+
+        Some(CartridgeType {
+            code,
+            mapper,
+            has_ram,
+            has_battery,
+            has_timer,
+            has_rumble,
+            has_sensor,
+        })
+    }
+
+    // Unlicensed mappers come without RAM or extra hardware, code is whatever the header says
+    pub fn unlicensed(code: u8, mapper: MapperType) -> CartridgeType {
+        CartridgeType {
+            code,
+            mapper,
+            has_ram: false,
+            has_battery: false,
+            has_timer: false,
+            has_rumble: false,
+            has_sensor: false,
+        }
+    }
+}
+
+impl fmt::Display for CartridgeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mapper: &str = match self.mapper {
+            MapperType::None => "ROM",
+            MapperType::Mbc1 => "MBC1",
+            MapperType::Mbc2 => "MBC2",
+            MapperType::Mmm01 => "MMM01",
+            MapperType::Mbc3 => "MBC3",
+            MapperType::Mbc5 => "MBC5",
+            MapperType::Mbc6 => "MBC6",
+            MapperType::Mbc7 => "MBC7",
+            MapperType::PocketCamera => "POCKET CAMERA",
+            MapperType::Tama5 => "BANDAI TAMA5",
+            MapperType::HuC3 => "HuC3",
+            MapperType::HuC1 => "HuC1",
+            MapperType::WisdomTree => "WISDOM TREE",
+            MapperType::M161 => "M161",
+            MapperType::SachenMmc1 => "SACHEN MMC1",
+            MapperType::SachenMmc2 => "SACHEN MMC2",
+        };
+        write!(f, "{mapper}")?;
+        if self.mapper == MapperType::None && !self.has_ram {
+            write!(f, " ONLY")?;
+        }
+        if self.has_timer && self.mapper == MapperType::Mbc3 {
+            write!(f, "+TIMER")?;
+        }
+        if self.has_sensor {
+            write!(f, "+SENSOR")?;
+        }
+        if self.has_rumble {
+            write!(f, "+RUMBLE")?;
+        }
+        if self.has_ram && self.mapper != MapperType::Mbc2 {
+            write!(f, "+RAM")?;
+        }
+        if self.has_battery {
+            write!(f, "+BATTERY")?;
+        }
+        write!(f, " (${:02X})", self.code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    None,
+    Enhanced,
+    Only,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    // Used when the old licensee code is 0x33
+    New(String),
+}
+
+impl fmt::Display for Licensee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Licensee::Old(code) => write!(f, "${code:02X}"),
+            Licensee::New(code) => write!(f, "\"{code}\""),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub supports_sgb: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub is_japanese: bool,
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, EmulatorError> {
+        if rom.len() < HEADER_END_ADDRESS {
+            return Err(EmulatorError::CartridgeTooSmall { size: rom.len() });
+        }
+
+        // MMM01 boots from a menu in the last 32 KiB, with its own header
+        let detected_mapper: Option<MapperType> = mapper::detect_misreported(rom);
+        let header: &[u8] = if detected_mapper == Some(MapperType::Mmm01) {
+            &rom[rom.len() - mmm01::MENU_SIZE..]
+        } else {
+            rom
+        };
+
+        let computed_header_checksum: u8 = header_checksum(header);
+        if header[0x014D] != computed_header_checksum {
+            return Err(EmulatorError::HeaderChecksumMismatch {
+                expected: header[0x014D],
+                computed: computed_header_checksum,
+            });
+        }
+
+        let cgb_support: CgbSupport = match header[0x0143] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+
+        // Later cartridges reuse the end of the title for a manufacturer code and the CGB flag
+        let manufacturer_code: Option<String> = if cgb_support != CgbSupport::None
+            && header[0x013F..=0x0142]
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        {
+            Some(String::from_utf8_lossy(&header[0x013F..=0x0142]).into_owned())
+        } else {
+            None
+        };
+        let title_end: usize = match (&manufacturer_code, cgb_support) {
+            (Some(_), _) => 0x013F,
+            (None, CgbSupport::None) => 0x0144,
+            (None, _) => 0x0143,
+        };
+        let title: String = header[0x0134..title_end]
+            .iter()
+            .take_while(|&&byte| byte != 0x00)
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '?'
+                }
+            })
+            .collect();
+
+        let cartridge_type: CartridgeType = match detected_mapper {
+            Some(MapperType::Mmm01) | None => CartridgeType::from_code(header[0x0147]).ok_or(
+                EmulatorError::UnknownCartridgeType {
+                    code: header[0x0147],
+                },
+            )?,
+            Some(mapper) => CartridgeType::unlicensed(header[0x0147], mapper),
+        };
+
+        let rom_size: usize = match header[0x0148] {
+            // Unlicensed cartridges cannot be trusted with their size
+            _ if !matches!(detected_mapper, Some(MapperType::Mmm01) | None) => rom.len(),
+            code @ 0x00..=0x08 => (2 * ROM_BANK_SIZE) << code,
+            // Unofficial sizes only listed in a few documents
+            0x52 => 72 * ROM_BANK_SIZE,
+            0x53 => 80 * ROM_BANK_SIZE,
+            0x54 => 96 * ROM_BANK_SIZE,
+            code => return Err(EmulatorError::UnknownRomSize { code }),
+        };
+
+        let ram_size: usize = match header[0x0149] {
+            0x00 => 0,
+            // Unofficial 2 KiB size only used by a few homebrews
+            0x01 => 0x0800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(EmulatorError::UnknownRamSize { code }),
+        };
+
+        let licensee: Licensee = if header[0x014B] == 0x33 {
+            Licensee::New(String::from_utf8_lossy(&header[0x0144..=0x0145]).into_owned())
+        } else {
+            Licensee::Old(header[0x014B])
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+            cgb_support,
+            supports_sgb: header[0x0146] == 0x03,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            is_japanese: header[0x014A] == 0x00,
+            licensee,
+            version: header[0x014C],
+            header_checksum: header[0x014D],
+            global_checksum: ((header[0x014E] as u16) << 8) | header[0x014F] as u16,
+        })
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Title:             {}", self.title)?;
+        if let Some(manufacturer_code) = &self.manufacturer_code {
+            writeln!(f, "Manufacturer code: {manufacturer_code}")?;
+        }
+        let cgb_support: &str = match self.cgb_support {
+            CgbSupport::None => "no",
+            CgbSupport::Enhanced => "enhanced",
+            CgbSupport::Only => "only",
+        };
+        writeln!(f, "CGB support:       {cgb_support}")?;
+        writeln!(
+            f,
+            "SGB support:       {}",
+            if self.supports_sgb { "yes" } else { "no" }
+        )?;
+        writeln!(f, "Cartridge type:    {}", self.cartridge_type)?;
+        writeln!(f, "ROM size:          {} KiB", self.rom_size / 1024)?;
+        writeln!(f, "RAM size:          {} KiB", self.ram_size / 1024)?;
+        writeln!(
+            f,
+            "Destination:       {}",
+            if self.is_japanese {
+                "Japan"
+            } else {
+                "overseas"
+            }
+        )?;
+        writeln!(f, "Licensee:          {}", self.licensee)?;
+        writeln!(f, "Version:           {}", self.version)?;
+        writeln!(f, "Header checksum:   ${:02X}", self.header_checksum)?;
+        write!(f, "Global checksum:   ${:04X}", self.global_checksum)
+    }
+}
+
+// Checked by the boot ROM, which locks up on a mismatch
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014C].iter().fold(0u8, |checksum, &byte| {
+        checksum.wrapping_sub(byte).wrapping_sub(1)
+    })
+}
+
+// Sum of every byte but the global checksum itself, never checked by the hardware
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|&(address, _)| address != 0x014E && address != 0x014F)
+        .fold(0u16, |checksum, (_, &byte)| {
+            checksum.wrapping_add(byte as u16)
+        })
+}
+
+#[derive(Debug)]
+pub struct Cartridge {
+    pub header: CartridgeHeader,
+    pub rom: Vec<u8>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Result<Cartridge, EmulatorError> {
+        let header: CartridgeHeader = CartridgeHeader::parse(&rom)?;
+        if rom.len() != header.rom_size {
+            return Err(EmulatorError::RomSizeMismatch {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+        Ok(Cartridge { header, rom })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Cartridge, EmulatorError> {
+        let rom: Vec<u8> = fs::read(path).map_err(|error| EmulatorError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        Cartridge::new(rom)
+    }
+
+    pub fn is_global_checksum_valid(&self) -> bool {
+        global_checksum(&self.rom) == self.header.global_checksum
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Builds a ROM of the size given by the rom_size code, with a valid header
+    pub fn rom_with_header(
+        title: &[u8],
+        cartridge_type: u8,
+        rom_size: u8,
+        ram_size: u8,
+    ) -> Vec<u8> {
+        let mut rom: Vec<u8> = vec![0; (2 * ROM_BANK_SIZE) << rom_size];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0147] = cartridge_type;
+        rom[0x0148] = rom_size;
+        rom[0x0149] = ram_size;
+        rom[0x014B] = 0x01;
+        rom[0x014D] = header_checksum(&rom);
+        let checksum: u16 = global_checksum(&rom);
+        rom[0x014E..=0x014F].copy_from_slice(&checksum.to_be_bytes());
+        rom
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let mut rom: Vec<u8> = rom_with_header(b"TETRIS", 0x03, 0x01, 0x02);
+        rom[0x0146] = 0x03;
+        rom[0x014C] = 0x01;
+        rom[0x014D] = header_checksum(&rom);
+        rom[0x0150] = 0xC3;
+        let cartridge: Cartridge = Cartridge::new(rom).unwrap();
+
+        assert_eq!(cartridge.header.title, "TETRIS");
+        assert_eq!(cartridge.header.manufacturer_code, None);
+        assert_eq!(cartridge.header.cgb_support, CgbSupport::None);
+        assert!(cartridge.header.supports_sgb);
+        assert_eq!(cartridge.header.cartridge_type.mapper, MapperType::Mbc1);
+        assert!(cartridge.header.cartridge_type.has_battery);
+        assert_eq!(
+            cartridge.header.cartridge_type.to_string(),
+            "MBC1+RAM+BATTERY ($03)"
+        );
+        assert_eq!(cartridge.header.rom_size, 0x10000);
+        assert_eq!(cartridge.header.ram_size, 0x2000);
+        assert!(cartridge.header.is_japanese);
+        assert_eq!(cartridge.header.licensee, Licensee::Old(0x01));
+        assert_eq!(cartridge.header.version, 0x01);
+        // The global checksum was computed before the code changed
+        assert!(!cartridge.is_global_checksum_valid());
+    }
+
+    #[test]
+    fn splits_the_manufacturer_code_from_cgb_titles() {
+        let mut rom: Vec<u8> = rom_with_header(b"POKEMON_SLVAAXE\xC0", 0x10, 0x00, 0x03);
+        rom[0x0144..=0x0145].copy_from_slice(b"01");
+        rom[0x014B] = 0x33;
+        rom[0x014D] = header_checksum(&rom);
+        let header: CartridgeHeader = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer_code, Some(String::from("AAXE")));
+        assert_eq!(header.cgb_support, CgbSupport::Only);
+        assert_eq!(header.licensee, Licensee::New(String::from("01")));
+        assert_eq!(
+            header.cartridge_type.to_string(),
+            "MBC3+TIMER+RAM+BATTERY ($10)"
+        );
+    }
+
+    #[test]
+    fn detects_mappers_the_header_misreports() {
+        let mut rom: Vec<u8> = rom_with_header(b"WISDOM TREE", 0xC0, 0x00, 0x00);
+        rom.resize(8 * ROM_BANK_SIZE, 0x00);
+        let header: CartridgeHeader = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.cartridge_type.mapper, MapperType::WisdomTree);
+        assert_eq!(header.rom_size, 8 * ROM_BANK_SIZE);
+
+        // Padding alone does not make a plain ROM a Wisdom Tree one
+        let mut rom: Vec<u8> = rom_with_header(b"TETRIS", 0x00, 0x00, 0x00);
+        rom.resize(4 * ROM_BANK_SIZE, 0xFF);
+        assert_eq!(
+            CartridgeHeader::parse(&rom).unwrap().cartridge_type.mapper,
+            MapperType::None
+        );
+
+        let mut rom: Vec<u8> = rom_with_header(b"MANI 4 IN 1", 0x10, 0x03, 0x00);
+        assert_eq!(
+            CartridgeHeader::parse(&rom).unwrap().cartridge_type.mapper,
+            MapperType::M161
+        );
+
+        // The same ROM with the Nintendo logo hidden where only the Sachen boot sequence reads it
+        rom[0x0184..0x01B4].copy_from_slice(&NINTENDO_LOGO);
+        assert_eq!(
+            CartridgeHeader::parse(&rom).unwrap().cartridge_type.mapper,
+            MapperType::SachenMmc1
+        );
+
+        // MMM01 headers come from the menu at the end
+        let game: Vec<u8> = rom_with_header(b"GAME", 0x01, 0x00, 0x00);
+        let mut menu: Vec<u8> = rom_with_header(b"MENU", 0x0D, 0x00, 0x03);
+        menu[0x0148] = 0x02;
+        menu[0x014D] = header_checksum(&menu);
+        let rom: Vec<u8> = [game.clone(), game.clone(), game, menu].concat();
+        let cartridge: Cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.header.title, "MENU");
+        assert_eq!(cartridge.header.cartridge_type.mapper, MapperType::Mmm01);
+        assert!(cartridge.header.cartridge_type.has_battery);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert_eq!(
+            Cartridge::new(vec![0; 0x100]).unwrap_err(),
+            EmulatorError::CartridgeTooSmall { size: 0x100 }
+        );
+
+        let mut rom: Vec<u8> = rom_with_header(b"BROKEN", 0x00, 0x00, 0x00);
+        rom[0x014D] ^= 0xFF;
+        assert!(matches!(
+            Cartridge::new(rom).unwrap_err(),
+            EmulatorError::HeaderChecksumMismatch { .. }
+        ));
+
+        let mut rom: Vec<u8> = rom_with_header(b"BROKEN", 0x00, 0x00, 0x00);
+        rom[0x0147] = 0x04;
+        rom[0x014D] = header_checksum(&rom);
+        assert_eq!(
+            Cartridge::new(rom).unwrap_err(),
+            EmulatorError::UnknownCartridgeType { code: 0x04 }
+        );
+
+        let mut rom: Vec<u8> = rom_with_header(b"BROKEN", 0x00, 0x00, 0x00);
+        rom[0x0149] = 0x06;
+        rom[0x014D] = header_checksum(&rom);
+        assert_eq!(
+            Cartridge::new(rom).unwrap_err(),
+            EmulatorError::UnknownRamSize { code: 0x06 }
+        );
+
+        let mut rom: Vec<u8> = rom_with_header(b"BROKEN", 0x01, 0x01, 0x00);
+        rom.truncate(2 * ROM_BANK_SIZE);
+        assert_eq!(
+            Cartridge::new(rom).unwrap_err(),
+            EmulatorError::RomSizeMismatch {
+                expected: 4 * ROM_BANK_SIZE,
+                actual: 2 * ROM_BANK_SIZE
+            }
+        );
+    }
+}
@@ -1,416 +1,17 @@
 // Instruction structure and names are based on https://gbdev.io/pandocs/CPU_Instruction_Set.html
 // Instructions handeling and timings are based on https://gekkio.fi/files/gb-docs/gbctr.pdf
-use core::{fmt, panic};
+use core::fmt;
 use std::fmt::Debug;
 
+use crate::alu;
+use crate::bus::Bus;
+use crate::error::EmulatorError;
+use crate::instruction::{
+    self, AluOp, AluOperand, Cond, Instruction, R8, R16, R16Mem, R16Stk, ShiftOp,
+};
+use crate::interrupts::Interrupt;
 use crate::memory::Memory;
-
-#[derive(Debug, Default)]
-pub struct Type0InstructionHandler {}
-
-impl Type0InstructionHandler {
-    fn handle_instruction(
-        &self,
-        instruction: u8,
-        cycle_counter: &mut u64,
-        registers: &mut Registers,
-    ) {
-        println!("Type 0 instruction: {instruction:08b}");
-    }
-}
-
-#[derive(Debug, Default)]
-pub struct Type1InstructionHandler {}
-
-impl Type1InstructionHandler {
-    fn handle_instruction(
-        &self,
-        instruction: u8,
-        cycle_counter: &mut u64,
-        registers: &mut Registers,
-        memory: &mut Memory,
-        is_halting: &mut bool,
-    ) {
-        println!("Type 1 instruction: {instruction:08b}");
-
-        // if destination_register and source_register == [hl]
-        if instruction == 0b01110110 {
-            // halt
-            println!("halt");
-            *cycle_counter += 1;
-            *is_halting = true
-        } else {
-            // ld r8, r8
-            println!("ld r8, r8");
-            *cycle_counter += 1;
-            let destination_register: u8 = (instruction & 0b00111000) >> 3;
-            let source_register_bits: u8 = instruction & 0b00000111;
-            let (source_register_value, _): (u8, bool) =
-                registers.get_r8_register_value(source_register_bits, memory);
-            registers.set_r8_register_value(destination_register, source_register_value, memory);
-        }
-    }
-}
-
-#[derive(Debug, Default)]
-pub struct Type2InstructionHandler {}
-
-impl Type2InstructionHandler {
-    fn handle_instruction(
-        &self,
-        instruction: u8,
-        cycle_counter: &mut u64,
-        registers: &mut Registers,
-        memory: &Memory,
-    ) {
-        println!("Type 2 instruction: {instruction:08b}");
-        let op_code: u8 = (instruction & 0b11111000) >> 3;
-        let operand: u8 = instruction & 0b00000111;
-
-        println!("Op code: {op_code:05b} Operand: {operand:03b}");
-        let (r8_register_value, was_hl_loaded) = registers.get_r8_register_value(operand, memory);
-
-        if was_hl_loaded {
-            *cycle_counter += 1
-        }
-
-        let old_a_value: u8 = registers.a;
-
-        match op_code {
-            0b10000 => {
-                // add a, r8
-                println!("add a, r8");
-                *cycle_counter += 1;
-                registers.a += r8_register_value;
-                registers.set_add_flags(old_a_value, registers.a)
-            }
-            0b10001 => {
-                // adc a, r8
-                println!("adc a, r8");
-                *cycle_counter += 1;
-                let carry_value: u8 = if registers.f & Flags::C as u8 != 0 {
-                    1
-                } else {
-                    0
-                };
-                registers.a += r8_register_value + carry_value;
-                registers.set_add_flags(old_a_value, registers.a)
-            }
-            0b10010 => {
-                // sub a, r8
-                println!("sub a, r8");
-                *cycle_counter += 1;
-                registers.a -= r8_register_value;
-                registers.set_sub_flags(old_a_value, registers.a)
-            }
-            0b10011 => {
-                // sbc a, r8
-                println!("sbc a, r8");
-                *cycle_counter += 1;
-                let carry_value: u8 = if registers.f & Flags::C as u8 != 0 {
-                    1
-                } else {
-                    0
-                };
-                registers.a -= r8_register_value - carry_value;
-                registers.set_sub_flags(old_a_value, registers.a)
-            }
-            0b10100 => {
-                // and , r8
-                println!("and a, r8");
-                *cycle_counter += 1;
-                registers.a &= r8_register_value;
-                registers.set_logical_flags(registers.a, true)
-            }
-            0b10101 => {
-                // xor a, r8
-                println!("xor a, r8");
-                *cycle_counter += 1;
-                registers.a ^= r8_register_value;
-                registers.set_logical_flags(registers.a, false)
-            }
-            0b10110 => {
-                // or a, r8
-                println!("or a, r8");
-                *cycle_counter += 1;
-                registers.a |= r8_register_value;
-                registers.set_logical_flags(registers.a, false)
-            }
-            0b10111 => {
-                // cp a, r8
-                println!("cp a, r8");
-                *cycle_counter += 1;
-                registers.set_sub_flags(old_a_value, registers.a - r8_register_value)
-            }
-            _ => panic!("Unknown op_code"),
-        }
-    }
-}
-
-#[derive(Debug, Default)]
-pub struct Type3InstructionHandler {}
-
-impl Type3InstructionHandler {
-    fn handle_instruction(
-        &self,
-        instruction: u8,
-        cycle_counter: &mut u64,
-        registers: &mut Registers,
-        memory: &mut Memory,
-    ) {
-        println!("Type 3 instruction: {instruction:08b}");
-
-        let old_a_value: u8 = registers.a;
-
-        // First sub block operations (a operations)
-        match instruction {
-            0b11000110 => {
-                // add am imm8
-                println!("add a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.a += n;
-                registers.set_add_flags(old_a_value, registers.a);
-            }
-            0b11001110 => {
-                // adc a, imm8
-                println!("adc a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                let carry_value: u8 = if registers.f & Flags::C as u8 != 0 {
-                    1
-                } else {
-                    0
-                };
-                registers.a += n + carry_value;
-                registers.set_add_flags(old_a_value, registers.a);
-            }
-            0b11010110 => {
-                // sub a, imm8
-                println!("sub a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.a -= n;
-                registers.set_sub_flags(old_a_value, registers.a);
-            }
-            0b11011110 => {
-                // sbc a, imm
-                println!("subc a, imm");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                let carry_value: u8 = if registers.f & Flags::C as u8 != 0 {
-                    1
-                } else {
-                    0
-                };
-                registers.a -= n - carry_value;
-                registers.set_sub_flags(old_a_value, registers.a);
-            }
-            0b11100110 => {
-                // and a,imm8
-                println!("and a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.a &= n;
-                registers.set_logical_flags(registers.a, true);
-            }
-            0b11101110 => {
-                // xor a, imm8
-                println!("xor a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.a ^= n;
-                registers.set_logical_flags(registers.a, false);
-            }
-            0b11110110 => {
-                // or a, imm8
-                println!("or a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.a |= n;
-                registers.set_logical_flags(registers.a, false);
-            }
-            0b11111110 => {
-                // cp a, imm8
-                println!("cp a, imm8");
-                *cycle_counter += 1;
-                let n: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                registers.set_sub_flags(old_a_value, registers.a - n);
-            }
-            _ => {}
-        }
-
-        // second sub block operations
-
-        // conditional operations
-        let conditional_op_code: u8 = instruction & 0b11100111;
-        match conditional_op_code {
-            0b1100000 => {
-                // ret cond
-                println!("ret cond");
-                *cycle_counter += 1;
-                let condition_bits: u8 = (instruction & 0b00011000) >> 3;
-
-                if registers.should_execute(condition_bits) {
-                    *cycle_counter += 1;
-                    let nn_lsb: u8 = memory.get_value_at_memory_address(registers.sp);
-                    registers.sp += 1;
-                    *cycle_counter += 1;
-
-                    let nn_msb: u8 = memory.get_value_at_memory_address(registers.sp);
-                    registers.sp += 1;
-                    *cycle_counter += 1;
-
-                    registers.pc = ((nn_msb as u16) << 8) | nn_lsb as u16;
-                    *cycle_counter += 1
-                } else {
-                    *cycle_counter += 1
-                }
-            }
-            0b11000010 => {
-                // jp cond, imm16
-                println!("jp cond, imm16");
-                *cycle_counter += 1;
-                let condition_bits: u8 = (instruction & 0b00011000) >> 3;
-
-                let nn_lsb: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                let nn_msb: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                if registers.should_execute(condition_bits) {
-                    *cycle_counter += 1;
-                    registers.pc = ((nn_msb as u16) << 8) | nn_lsb as u16;
-                }
-            }
-            0b11000100 => {
-                // call cond, imm16
-                println!("call cond, imm16");
-                *cycle_counter += 1;
-                let condition_bits: u8 = (instruction & 0b00011000) >> 3;
-
-                let nn_lsb: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                let nn_msb: u8 = memory.get_value_at_memory_address(registers.pc);
-                registers.pc += 1;
-                *cycle_counter += 1;
-
-                if registers.should_execute(condition_bits) {
-                    registers.sp -= 1;
-                    let pc_msb: u8 = ((registers.pc & 0b1111111100000000) >> 8) as u8;
-                    memory.set_value_at_memory_address(registers.sp, pc_msb);
-                    registers.sp -= 1;
-                    *cycle_counter += 1;
-
-                    let pc_lsb: u8 = (registers.pc & 0b0000000011111111) as u8;
-                    memory.set_value_at_memory_address(registers.sp, pc_lsb);
-                    *cycle_counter += 1;
-
-                    registers.pc = ((nn_msb as u16) << 8) | nn_lsb as u16;
-                    *cycle_counter += 1
-                }
-            }
-            _ => {}
-        }
-
-        // target operation
-        let target_op_code: u8 = instruction & 0b11000111;
-        if target_op_code == 0b11000111 {
-            // rst tgt3
-            println!("rst tgt3");
-            *cycle_counter += 1;
-
-            let target: u8 = (instruction & 0b00111000) >> 3;
-            registers.sp -= 1;
-            *cycle_counter += 1;
-
-            let pc_msb: u8 = ((registers.pc & 0b1111111100000000) >> 8) as u8;
-            memory.set_value_at_memory_address(registers.sp, pc_msb);
-            registers.sp -= 1;
-            *cycle_counter += 1;
-
-            let pc_lsb: u8 = (registers.pc & 0b0000000011111111) as u8;
-            memory.set_value_at_memory_address(registers.sp, pc_lsb);
-            registers.pc = (target as u16) * 8;
-            *cycle_counter += 1
-        }
-
-        // other sub block 2 operations
-        // TODO:
-
-        // 3rd block operations (register operations)
-        let register_op_code: u8 = instruction & 0b11001111;
-        let register_bits: u8 = (instruction & 0b00110000) >> 4;
-
-        match register_op_code {
-            0b11000001 => {
-                // pop r16stk
-                println!("pop r16stk");
-                *cycle_counter += 1;
-
-                let lsb: u8 = memory.get_value_at_memory_address(registers.sp);
-                registers.sp += 1;
-                *cycle_counter += 1;
-
-                let msb: u8 = memory.get_value_at_memory_address(registers.sp);
-                registers.sp += 1;
-                *cycle_counter += 1;
-
-                let new_register_value: u16 = ((msb as u16) << 8) | lsb as u16;
-                registers.set_r16_register_stack_value(register_bits, new_register_value);
-            }
-            0b11000101 => {
-                // push r16stk
-                println!("push r16stk");
-                *cycle_counter += 1;
-
-                registers.sp -= 1;
-                *cycle_counter += 1;
-
-                let register_value: u16 = registers.get_r16_register_stack_value(register_bits);
-                let register_value_msb: u8 = ((register_value & 0b1111111100000000) >> 8) as u8;
-                let register_value_lsb: u8 = (register_value & 0b0000000011111111) as u8;
-                memory.set_value_at_memory_address(registers.sp, register_value_msb);
-                *cycle_counter += 1;
-
-                registers.sp -= 1;
-                memory.set_value_at_memory_address(registers.sp, register_value_lsb);
-                *cycle_counter += 1;
-            }
-            _ => {}
-        }
-        // TODO: Prefix and remaining operation sub blocks
-    }
-}
+use crate::model::Model;
 
 #[derive(Default)]
 pub struct Registers {
@@ -437,262 +38,1320 @@ impl fmt::Debug for Registers {
 }
 
 impl Registers {
+    // Registers once the boot ROM handed over control, some of them depend on the cartridge header
+    pub fn post_boot<B: Bus>(model: Model, bus: &mut B) -> Registers {
+        let header_checksum: u8 = bus.read(0x014D);
+        let is_cgb_cartridge: bool = bus.read(0x0143) & 0b10000000 != 0;
+
+        let (a, f, b, c, d, e, h, l): (u8, u8, u8, u8, u8, u8, u8, u8) = match model {
+            Model::Dmg0 => (0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            Model::Dmg | Model::Mgb => {
+                let a: u8 = if model == Model::Mgb { 0xFF } else { 0x01 };
+                // H and C are only set when the header checksum is not 0
+                let f: u8 = if header_checksum == 0 { 0x80 } else { 0xB0 };
+                (a, f, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D)
+            }
+            Model::Sgb | Model::Sgb2 => {
+                let a: u8 = if model == Model::Sgb2 { 0xFF } else { 0x01 };
+                (a, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60)
+            }
+            Model::Cgb | Model::Agb => {
+                if is_cgb_cartridge {
+                    (0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D)
+                } else {
+                    // In DMG compatibility mode, Nintendo licensed titles get their title checksum in b
+                    let old_licensee_code: u8 = bus.read(0x014B);
+                    let new_licensee_code: [u8; 2] = [bus.read(0x0144), bus.read(0x0145)];
+                    let is_nintendo_title: bool = old_licensee_code == 0x01
+                        || (old_licensee_code == 0x33 && new_licensee_code == *b"01");
+
+                    if is_nintendo_title {
+                        let title_checksum: u8 = (0x0134..=0x0143)
+                            .fold(0u8, |sum, address| sum.wrapping_add(bus.read(address)));
+                        (0x11, 0x80, title_checksum, 0x00, 0x00, 0x08, 0x99, 0x1A)
+                    } else {
+                        (0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C)
+                    }
+                }
+            }
+        };
+
+        let (b, f): (u8, u8) = if model == Model::Agb {
+            // The AGB boot ROM ends with an extra inc b
+            let b: u8 = b.wrapping_add(1);
+            (
+                b,
+                if b & 0b00001111 == 0 {
+                    Flags::H as u8
+                } else {
+                    0
+                },
+            )
+        } else {
+            (b, f)
+        };
+
+        Registers {
+            a,
+            f,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
     fn get_hl_value(&self) -> u16 {
         ((self.h as u16) << 8) | self.l as u16
     }
 
-    fn get_r8_register_value(&self, register_bits: u8, memory: &Memory) -> (u8, bool) {
-        let was_hl_loaded: bool = register_bits == 0b110;
-        let register_value: u8 = match register_bits {
-            0b000 => self.b,
-            0b001 => self.c,
-            0b010 => self.d,
-            0b011 => self.e,
-            0b100 => self.h,
-            0b101 => self.l,
-            0b110 => {
+    fn get_r8_register_value<B: Bus>(&self, register: R8, bus: &mut B) -> u8 {
+        match register {
+            R8::B => self.b,
+            R8::C => self.c,
+            R8::D => self.d,
+            R8::E => self.e,
+            R8::H => self.h,
+            R8::L => self.l,
+            R8::HlMem => {
                 // Loading the value at memory address [hl]
                 let memory_address: u16 = self.get_hl_value();
-                memory.get_value_at_memory_address(memory_address)
+                bus.read(memory_address)
             }
-            0b111 => self.a,
-            _ => panic!("Invalid register"),
-        };
-        (register_value, was_hl_loaded)
-    }
-
-    fn set_r8_register_value(&mut self, register_bits: u8, value: u8, memory: &mut Memory) {
-        match register_bits {
-            0b000 => self.b = value,
-            0b001 => self.c = value,
-            0b010 => self.d = value,
-            0b011 => self.e = value,
-            0b100 => self.h = value,
-            0b101 => self.l = value,
-            0b110 => {
+            R8::A => self.a,
+        }
+    }
+
+    fn set_r8_register_value<B: Bus>(&mut self, register: R8, value: u8, bus: &mut B) {
+        match register {
+            R8::B => self.b = value,
+            R8::C => self.c = value,
+            R8::D => self.d = value,
+            R8::E => self.e = value,
+            R8::H => self.h = value,
+            R8::L => self.l = value,
+            R8::HlMem => {
                 let memory_address: u16 = self.get_hl_value();
-                memory.set_value_at_memory_address(memory_address, value);
+                bus.write(memory_address, value);
             }
-            0b111 => self.a = value,
-            _ => panic!("Invalid register"),
+            R8::A => self.a = value,
         }
     }
 
     // TODO: Return value as u16 or direcly msb / lsb ?
-    fn get_r16_register_value(&self, register_bits: u8) -> u16 {
-        match register_bits {
-            0b00 => ((self.b as u16) << 8) | self.c as u16, // BC
-            0b01 => ((self.d as u16) << 8) | self.e as u16, // DE
-            0b10 => ((self.h as u16) << 8) | self.l as u16, // HL
-            0b11 => self.sp,
-            _ => panic!("Invalid register"),
+    fn get_r16_register_value(&self, register: R16) -> u16 {
+        match register {
+            R16::Bc => ((self.b as u16) << 8) | self.c as u16,
+            R16::De => ((self.d as u16) << 8) | self.e as u16,
+            R16::Hl => ((self.h as u16) << 8) | self.l as u16,
+            R16::Sp => self.sp,
         }
     }
 
     // TODO: Pass value as u16 or direcly msb / lsb ?
-    fn set_r16_register_value(&mut self, register_bits: u8, value: u16) {
+    fn set_r16_register_value(&mut self, register: R16, value: u16) {
         let value_lsb: u8 = (value & 0b0000000011111111) as u8;
         let value_msb: u8 = ((value & 0b1111111100000000) >> 8) as u8;
-        match register_bits {
-            0b00 => {
-                // BC
+        match register {
+            R16::Bc => {
                 self.b = value_msb;
                 self.c = value_lsb
             }
-            0b01 => {
-                // DE
+            R16::De => {
                 self.d = value_msb;
                 self.e = value_lsb
             }
-            0b10 => {
-                // HL
+            R16::Hl => {
                 self.h = value_msb;
                 self.l = value_lsb
             }
-            0b11 => {
-                // SP
+            R16::Sp => {
                 self.sp = value;
             }
-            _ => panic!("Invalid register"),
         }
     }
 
-    fn get_r16_register_stack_value(&self, register_bits: u8) -> u16 {
-        match register_bits {
-            0b00 => ((self.b as u16) << 8) | self.c as u16, // BC
-            0b01 => ((self.d as u16) << 8) | self.e as u16, // DE
-            0b10 => ((self.h as u16) << 8) | self.l as u16, // HL
-            0b11 => ((self.a as u16) << 8) | self.f as u16, // AF
-            _ => panic!("Invalid register"),
+    fn get_r16_register_stack_value(&self, register: R16Stk) -> u16 {
+        match register {
+            R16Stk::Bc => ((self.b as u16) << 8) | self.c as u16,
+            R16Stk::De => ((self.d as u16) << 8) | self.e as u16,
+            R16Stk::Hl => ((self.h as u16) << 8) | self.l as u16,
+            R16Stk::Af => ((self.a as u16) << 8) | self.f as u16,
         }
     }
 
-    fn set_r16_register_stack_value(&mut self, register_bits: u8, value: u16) {
+    fn set_r16_register_stack_value(&mut self, register: R16Stk, value: u16) {
         let value_lsb: u8 = (value & 0b0000000011111111) as u8;
         let value_msb: u8 = ((value & 0b1111111100000000) >> 8) as u8;
-        match register_bits {
-            0b00 => {
-                // BC
+        match register {
+            R16Stk::Bc => {
                 self.b = value_msb;
                 self.c = value_lsb
             }
-            0b01 => {
-                // DE
+            R16Stk::De => {
                 self.d = value_msb;
                 self.e = value_lsb
             }
-            0b10 => {
-                // HL
+            R16Stk::Hl => {
                 self.h = value_msb;
                 self.l = value_lsb
             }
-            0b11 => {
-                // AF
+            R16Stk::Af => {
+                // The lower nibble of f is always 0
                 self.a = value_msb;
-                self.f = value_lsb
+                self.f = value_lsb & 0b11110000
             }
-            _ => panic!("Invalid register"),
         }
     }
 
-    // TODO: getter and setter for r16_register_memory_value
+    // Returns the address pointed by a r16mem register, incrementing or decrementing hl for [hl+] and [hl-]
+    fn get_r16_register_memory_address(&mut self, register: R16Mem) -> u16 {
+        match register {
+            R16Mem::Bc => ((self.b as u16) << 8) | self.c as u16,
+            R16Mem::De => ((self.d as u16) << 8) | self.e as u16,
+            R16Mem::HlInc => {
+                let hl_value: u16 = self.get_hl_value();
+                self.set_r16_register_value(R16::Hl, hl_value.wrapping_add(1));
+                hl_value
+            }
+            R16Mem::HlDec => {
+                let hl_value: u16 = self.get_hl_value();
+                self.set_r16_register_value(R16::Hl, hl_value.wrapping_sub(1));
+                hl_value
+            }
+        }
+    }
 
-    fn set_add_flags(&mut self, old_value: u8, new_value: u8) {
-        self.f = 0b00000000;
+    fn is_flag_set(&self, flag: Flags) -> bool {
+        self.f & flag as u8 != 0
+    }
 
-        if new_value == 0 {
-            self.f |= Flags::Z as u8
+    fn set_flag(&mut self, flag: Flags, is_set: bool) {
+        if is_set {
+            self.f |= flag as u8
+        } else {
+            self.f &= !(flag as u8)
         }
+    }
 
-        let old_value_lsb: u8 = old_value & 0b00001111;
-        let new_value_lsb: u8 = new_value & 0b00001111;
+    fn should_execute(&self, condition: Option<Cond>) -> bool {
+        match condition {
+            None => true,
+            Some(Cond::Nz) => !self.is_flag_set(Flags::Z),
+            Some(Cond::Z) => self.is_flag_set(Flags::Z),
+            Some(Cond::Nc) => !self.is_flag_set(Flags::C),
+            Some(Cond::C) => self.is_flag_set(Flags::C),
+        }
+    }
+}
+
+pub enum Flags {
+    C = 0b00010000, // Carry flag (bit 7 or 15)
+    H = 0b00100000, // Half carry flag (bit 3 or 11)
+    N = 0b01000000, // Substraction flag
+    Z = 0b10000000, // Zero flag
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    #[default]
+    Running,
+    Halted,
+    Stopped,
+    // An illegal op code hard locked the CPU, only a reset recovers from it
+    Locked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Instruction {
+        op_code: u8,
+        instruction: Instruction,
+    },
+    Interrupt {
+        handler_address: u16,
+    },
+    // The CPU is halted, stopped or locked and waited for one M-cycle
+    Idle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    pub pc: u16, // pc before the step
+    pub kind: StepKind,
+    pub m_cycles: u64,
+    pub state: CpuState, // state after the step
+}
+
+impl StepResult {
+    pub fn t_cycles(&self) -> u64 {
+        self.m_cycles * 4
+    }
+}
+
+#[derive(Default)]
+pub struct Cpu<B: Bus = Memory> {
+    pub state: CpuState,
+    // Why the CPU entered CpuState::Locked
+    pub lock_error: Option<EmulatorError>,
+    pub interrupt_master_enable: bool,
+    // ei enables IME only after the following instruction, counts down the instructions left
+    pub interrupt_master_enable_delay: u8,
+    // halt with IME=0 and an interrupt pending fails to increment pc on the next op code fetch
+    pub is_halt_bug_active: bool,
+    pub cycle_counter: u64,
+    pub registers: Registers,
+    pub bus: B,
+}
 
-        if new_value_lsb < old_value_lsb {
-            self.f |= Flags::H as u8
+impl<B: Bus + Debug> Debug for Cpu<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "========== CPU ==========\n===== Registers =====\n{:?}\n===== Memory =====\n{:?}",
+            self.registers, self.bus
+        )
+    }
+}
+
+impl Cpu<Memory> {
+    // Builds a CPU in the state left by the boot ROM of model
+    // memory must be built for the same model and already hold the cartridge
+    pub fn post_boot(model: Model, mut memory: Memory) -> Cpu<Memory> {
+        memory.apply_post_boot_state(model);
+        Cpu {
+            registers: Registers::post_boot(model, &mut memory),
+            bus: memory,
+            ..Cpu::default()
+        }
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    // Advances the rest of the system by one M-cycle
+    fn tick(&mut self) {
+        self.cycle_counter += 1;
+        self.bus.tick(self.cycle_counter)
+    }
+
+    // Every memory access takes one M-cycle
+    fn read_memory(&mut self, memory_address: u16) -> u8 {
+        self.tick();
+        self.bus.read(memory_address)
+    }
+
+    fn write_memory(&mut self, memory_address: u16, value: u8) {
+        self.tick();
+        self.bus.write(memory_address, value)
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let value: u8 = self.read_memory(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        value
+    }
+
+    fn read_r8(&mut self, register: R8) -> u8 {
+        if register == R8::HlMem {
+            self.tick()
         }
+        self.registers
+            .get_r8_register_value(register, &mut self.bus)
+    }
 
-        if new_value < old_value {
-            self.f |= Flags::C as u8
+    fn write_r8(&mut self, register: R8, value: u8) {
+        if register == R8::HlMem {
+            self.tick()
         }
+        self.registers
+            .set_r8_register_value(register, value, &mut self.bus)
     }
 
-    fn set_sub_flags(&mut self, old_value: u8, new_value: u8) {
-        self.f = 0b00000000;
+    fn push_u16(&mut self, value: u16) {
+        // The stack pointer is decremented during an internal cycle before the writes
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.tick();
+
+        let value_msb: u8 = ((value & 0b1111111100000000) >> 8) as u8;
+        self.write_memory(self.registers.sp, value_msb);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+
+        let value_lsb: u8 = (value & 0b0000000011111111) as u8;
+        self.write_memory(self.registers.sp, value_lsb);
+    }
+
+    fn pop_u16(&mut self) -> u16 {
+        let lsb: u8 = self.read_memory(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+
+        let msb: u8 = self.read_memory(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
 
-        self.f |= Flags::N as u8;
+        ((msb as u16) << 8) | lsb as u16
+    }
 
-        if new_value == 0 {
-            self.f |= Flags::Z as u8
+    // Reads the op code at pc and its operands, each byte fetched takes one M-cycle
+    pub fn fetch_instruction(&mut self) -> (u8, Instruction) {
+        let op_code: u8 = if self.is_halt_bug_active {
+            self.is_halt_bug_active = false;
+            self.read_memory(self.registers.pc)
+        } else {
+            self.fetch_byte()
+        };
+        let length: usize = instruction::instruction_length(op_code);
+        let mut bytes: [u8; 3] = [op_code, 0, 0];
+        for byte in bytes.iter_mut().take(length).skip(1) {
+            *byte = self.fetch_byte();
         }
+        (op_code, instruction::decode(&bytes[..length]))
+    }
 
-        let old_value_lsb: u8 = old_value & 0b00001111;
-        let new_value_lsb: u8 = new_value & 0b00001111;
+    // Executes a decoded instruction, the cycles spent fetching it are already counted
+    pub fn execute(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Nop => {}
+            Instruction::LdR16Imm16 { dst, value } => {
+                self.registers.set_r16_register_value(dst, value);
+            }
+            Instruction::LdR16MemA { dst } => {
+                let memory_address: u16 = self.registers.get_r16_register_memory_address(dst);
+                self.write_memory(memory_address, self.registers.a);
+            }
+            Instruction::LdAR16Mem { src } => {
+                let memory_address: u16 = self.registers.get_r16_register_memory_address(src);
+                self.registers.a = self.read_memory(memory_address);
+            }
+            Instruction::LdImm16Sp { address } => {
+                let sp_lsb: u8 = (self.registers.sp & 0b0000000011111111) as u8;
+                self.write_memory(address, sp_lsb);
 
-        if new_value_lsb > old_value_lsb {
-            self.f |= Flags::H as u8
+                let sp_msb: u8 = ((self.registers.sp & 0b1111111100000000) >> 8) as u8;
+                self.write_memory(address.wrapping_add(1), sp_msb);
+            }
+            Instruction::IncR16(register) => {
+                let register_value: u16 = self.registers.get_r16_register_value(register);
+                self.registers
+                    .set_r16_register_value(register, register_value.wrapping_add(1));
+                self.tick();
+            }
+            Instruction::DecR16(register) => {
+                let register_value: u16 = self.registers.get_r16_register_value(register);
+                self.registers
+                    .set_r16_register_value(register, register_value.wrapping_sub(1));
+                self.tick();
+            }
+            Instruction::AddHlR16(register) => {
+                let hl_value: u16 = self.registers.get_hl_value();
+                let register_value: u16 = self.registers.get_r16_register_value(register);
+                let (new_hl_value, new_flags): (u16, u8) =
+                    alu::add16(hl_value, register_value, self.registers.f);
+                self.registers.set_r16_register_value(R16::Hl, new_hl_value);
+                self.registers.f = new_flags;
+                self.tick();
+            }
+            Instruction::IncR8(register) => {
+                let register_value: u8 = self.read_r8(register);
+                let (new_register_value, new_flags): (u8, u8) =
+                    alu::inc(register_value, self.registers.f);
+                self.write_r8(register, new_register_value);
+                self.registers.f = new_flags;
+            }
+            Instruction::DecR8(register) => {
+                let register_value: u8 = self.read_r8(register);
+                let (new_register_value, new_flags): (u8, u8) =
+                    alu::dec(register_value, self.registers.f);
+                self.write_r8(register, new_register_value);
+                self.registers.f = new_flags;
+            }
+            Instruction::LdR8Imm8 { dst, value } => {
+                self.write_r8(dst, value);
+            }
+            Instruction::Rlca | Instruction::Rrca | Instruction::Rla | Instruction::Rra => {
+                let op: ShiftOp = match instruction {
+                    Instruction::Rlca => ShiftOp::Rlc,
+                    Instruction::Rrca => ShiftOp::Rrc,
+                    Instruction::Rla => ShiftOp::Rl,
+                    _ => ShiftOp::Rr,
+                };
+                let (new_a_value, new_flags): (u8, u8) =
+                    alu::shift(op, self.registers.a, self.registers.f);
+                self.registers.a = new_a_value;
+                // rlca, rrca, rla and rra always reset the zero flag
+                self.registers.f = new_flags & !(Flags::Z as u8);
+            }
+            Instruction::Daa => {
+                (self.registers.a, self.registers.f) = alu::daa(self.registers.a, self.registers.f)
+            }
+            Instruction::Cpl => {
+                self.registers.a = !self.registers.a;
+                self.registers.set_flag(Flags::N, true);
+                self.registers.set_flag(Flags::H, true);
+            }
+            Instruction::Scf => {
+                self.registers.set_flag(Flags::N, false);
+                self.registers.set_flag(Flags::H, false);
+                self.registers.set_flag(Flags::C, true);
+            }
+            Instruction::Ccf => {
+                let carry: bool = self.registers.is_flag_set(Flags::C);
+                self.registers.set_flag(Flags::N, false);
+                self.registers.set_flag(Flags::H, false);
+                self.registers.set_flag(Flags::C, !carry);
+            }
+            Instruction::Jr { cond, offset } => {
+                if self.registers.should_execute(cond) {
+                    self.registers.pc = self.registers.pc.wrapping_add_signed(offset as i16);
+                    self.tick();
+                }
+            }
+            Instruction::Stop => {
+                self.state = CpuState::Stopped;
+            }
+            Instruction::Ld8 { dst, src } => {
+                let register_value: u8 = self.read_r8(src);
+                self.write_r8(dst, register_value);
+            }
+            Instruction::Halt => {
+                if !self.interrupt_master_enable && self.bus.pending_interrupts() != 0 {
+                    self.is_halt_bug_active = true;
+                } else {
+                    self.state = CpuState::Halted;
+                }
+            }
+            Instruction::Alu { op, operand } => {
+                let n: u8 = match operand {
+                    AluOperand::R8(register) => self.read_r8(register),
+                    AluOperand::Imm8(value) => value,
+                };
+                let a: u8 = self.registers.a;
+                let f: u8 = self.registers.f;
+                match op {
+                    AluOp::Add => (self.registers.a, self.registers.f) = alu::add(a, n),
+                    AluOp::Adc => (self.registers.a, self.registers.f) = alu::adc(a, n, f),
+                    AluOp::Sub => (self.registers.a, self.registers.f) = alu::sub(a, n),
+                    AluOp::Sbc => (self.registers.a, self.registers.f) = alu::sbc(a, n, f),
+                    AluOp::And => (self.registers.a, self.registers.f) = alu::and(a, n),
+                    AluOp::Xor => (self.registers.a, self.registers.f) = alu::xor(a, n),
+                    AluOp::Or => (self.registers.a, self.registers.f) = alu::or(a, n),
+                    AluOp::Cp => self.registers.f = alu::cp(a, n),
+                }
+            }
+            Instruction::Ret { cond } => {
+                if cond.is_some() {
+                    // Conditional returns check the condition during an internal cycle
+                    self.tick();
+                }
+                if self.registers.should_execute(cond) {
+                    self.registers.pc = self.pop_u16();
+                    self.tick();
+                }
+            }
+            Instruction::Reti => {
+                self.registers.pc = self.pop_u16();
+                self.tick();
+                self.interrupt_master_enable = true;
+            }
+            Instruction::Jp { cond, address } => {
+                if self.registers.should_execute(cond) {
+                    self.registers.pc = address;
+                    self.tick();
+                }
+            }
+            Instruction::JpHl => {
+                self.registers.pc = self.registers.get_hl_value();
+            }
+            Instruction::Call { cond, address } => {
+                if self.registers.should_execute(cond) {
+                    self.push_u16(self.registers.pc);
+                    self.registers.pc = address;
+                }
+            }
+            Instruction::Rst { target } => {
+                self.push_u16(self.registers.pc);
+                self.registers.pc = target as u16;
+            }
+            Instruction::Pop(register) => {
+                let register_value: u16 = self.pop_u16();
+                self.registers
+                    .set_r16_register_stack_value(register, register_value);
+            }
+            Instruction::Push(register) => {
+                let register_value: u16 = self.registers.get_r16_register_stack_value(register);
+                self.push_u16(register_value);
+            }
+            Instruction::LdhCA => {
+                let memory_address: u16 = 0xFF00 | self.registers.c as u16;
+                self.write_memory(memory_address, self.registers.a);
+            }
+            Instruction::LdhImm8A { offset } => {
+                let memory_address: u16 = 0xFF00 | offset as u16;
+                self.write_memory(memory_address, self.registers.a);
+            }
+            Instruction::LdImm16A { address } => {
+                self.write_memory(address, self.registers.a);
+            }
+            Instruction::LdhAC => {
+                let memory_address: u16 = 0xFF00 | self.registers.c as u16;
+                self.registers.a = self.read_memory(memory_address);
+            }
+            Instruction::LdhAImm8 { offset } => {
+                let memory_address: u16 = 0xFF00 | offset as u16;
+                self.registers.a = self.read_memory(memory_address);
+            }
+            Instruction::LdAImm16 { address } => {
+                self.registers.a = self.read_memory(address);
+            }
+            Instruction::AddSpImm8 { offset } => {
+                (self.registers.sp, self.registers.f) =
+                    alu::add_sp_e8(self.registers.sp, offset as u8);
+                self.tick();
+                self.tick();
+            }
+            Instruction::LdHlSpImm8 { offset } => {
+                let (new_hl_value, new_flags): (u16, u8) =
+                    alu::add_sp_e8(self.registers.sp, offset as u8);
+                self.registers.set_r16_register_value(R16::Hl, new_hl_value);
+                self.registers.f = new_flags;
+                self.tick();
+            }
+            Instruction::LdSpHl => {
+                self.registers.sp = self.registers.get_hl_value();
+                self.tick();
+            }
+            Instruction::Di => {
+                self.interrupt_master_enable = false;
+                self.interrupt_master_enable_delay = 0;
+            }
+            Instruction::Ei => {
+                if !self.interrupt_master_enable && self.interrupt_master_enable_delay == 0 {
+                    self.interrupt_master_enable_delay = 2;
+                }
+            }
+            Instruction::Shift { op, operand } => {
+                let register_value: u8 = self.read_r8(operand);
+                let (new_register_value, new_flags): (u8, u8) =
+                    alu::shift(op, register_value, self.registers.f);
+                self.write_r8(operand, new_register_value);
+                self.registers.f = new_flags;
+            }
+            Instruction::Bit { bit, operand } => {
+                let register_value: u8 = self.read_r8(operand);
+                let is_bit_set: bool = register_value & (1 << bit) != 0;
+                self.registers.set_flag(Flags::Z, !is_bit_set);
+                self.registers.set_flag(Flags::N, false);
+                self.registers.set_flag(Flags::H, true);
+            }
+            Instruction::Res { bit, operand } => {
+                let register_value: u8 = self.read_r8(operand);
+                self.write_r8(operand, register_value & !(1 << bit));
+            }
+            Instruction::Set { bit, operand } => {
+                let register_value: u8 = self.read_r8(operand);
+                self.write_r8(operand, register_value | (1 << bit));
+            }
+            Instruction::Illegal(op_code) => {
+                self.state = CpuState::Locked;
+                self.lock_error = Some(EmulatorError::IllegalOpCode {
+                    op_code,
+                    address: self.registers.pc.wrapping_sub(1),
+                });
+            }
+        }
+    }
+
+    fn handle_instruction(&mut self) -> StepKind {
+        let (op_code, instruction): (u8, Instruction) = self.fetch_instruction();
+        self.execute(instruction);
+
+        if self.interrupt_master_enable_delay > 0 {
+            self.interrupt_master_enable_delay -= 1;
+            if self.interrupt_master_enable_delay == 0 {
+                self.interrupt_master_enable = true;
+            }
         }
 
-        if new_value > old_value {
-            self.f |= Flags::C as u8
+        StepKind::Instruction {
+            op_code,
+            instruction,
         }
     }
 
-    fn set_logical_flags(&mut self, new_value: u8, is_and: bool) {
-        self.f = 0b00000000;
+    // Dispatches the highest priority pending interrupt and returns the address jumped to
+    // Timings are based on https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
+    fn handle_interrupts(&mut self) -> Option<u16> {
+        if !self.interrupt_master_enable || self.bus.pending_interrupts() == 0 {
+            return None;
+        }
+
+        self.interrupt_master_enable = false;
+        self.tick();
+        self.tick();
+
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        let pc_msb: u8 = ((self.registers.pc & 0b1111111100000000) >> 8) as u8;
+        self.write_memory(self.registers.sp, pc_msb);
+
+        // Pushing the msb can overwrite IE, the interrupt is then picked after this write
+        let interrupt: Option<Interrupt> = Interrupt::from_pending(self.bus.pending_interrupts());
+
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        let pc_lsb: u8 = (self.registers.pc & 0b0000000011111111) as u8;
+        self.write_memory(self.registers.sp, pc_lsb);
+
+        self.registers.pc = match interrupt {
+            Some(interrupt) => {
+                self.bus.acknowledge_interrupt(interrupt);
+                interrupt.handler_address()
+            }
+            // The dispatch is cancelled and jumps to 0x0000
+            None => 0x0000,
+        };
+        self.tick();
+
+        Some(self.registers.pc)
+    }
+
+    // Executes exactly one instruction or interrupt dispatch, or waits one M-cycle when halted or stopped
+    pub fn step(&mut self) -> StepResult {
+        let pc: u16 = self.registers.pc;
+        let start_cycle_counter: u64 = self.cycle_counter;
 
-        if new_value == 0 {
-            self.f |= Flags::Z as u8
+        // halt exits as soon as an interrupt is pending, even with IME=0
+        if self.state == CpuState::Halted && self.bus.pending_interrupts() != 0 {
+            self.state = CpuState::Running;
         }
 
-        if is_and {
-            self.f |= Flags::H as u8
+        let kind: StepKind = match self.state {
+            CpuState::Running => match self.handle_interrupts() {
+                Some(handler_address) => StepKind::Interrupt { handler_address },
+                None => self.handle_instruction(),
+            },
+            // A halted CPU waits for the next event of the bus at once
+            CpuState::Halted => {
+                self.cycle_counter += self.bus.get_idle_m_cycles(self.cycle_counter);
+                self.bus.tick(self.cycle_counter);
+                StepKind::Idle
+            }
+            CpuState::Stopped | CpuState::Locked => {
+                self.tick();
+                StepKind::Idle
+            }
+        };
+
+        StepResult {
+            pc,
+            kind,
+            m_cycles: self.cycle_counter - start_cycle_counter,
+            state: self.state,
         }
     }
 
-    fn should_execute(&self, condition_bits: u8) -> bool {
-        match condition_bits {
-            0b00 => (self.f & Flags::Z as u8) == 0, // NZ
-            0b01 => (self.f & Flags::Z as u8) != 0, // Z
-            0b10 => (self.f & Flags::C as u8) == 0, // NC
-            0b11 => (self.f & Flags::C as u8) != 0, // C
-            _ => panic!("Invalid condition bits"),
+    // Steps until at least m_cycles M-cycles have elapsed, returns the number of M-cycles actually run
+    pub fn run_for_cycles(&mut self, m_cycles: u64) -> u64 {
+        let start_cycle_counter: u64 = self.cycle_counter;
+        while self.cycle_counter - start_cycle_counter < m_cycles {
+            self.step();
         }
+        self.cycle_counter - start_cycle_counter
     }
-}
 
-enum Flags {
-    C = 0b00010000, // Carry flag (bit 7 or 15)
-    H = 0b00100000, // Half carry flag (bit 3 or 11)
-    N = 0b01000000, // Substraction flag
-    Z = 0b10000000, // Zero flag
-}
+    // Steps until the predicate returns true for the cpu and the last step, returns that step
+    pub fn run_until<P>(&mut self, mut predicate: P) -> StepResult
+    where
+        P: FnMut(&mut Cpu<B>, &StepResult) -> bool,
+    {
+        loop {
+            let step_result: StepResult = self.step();
+            if predicate(self, &step_result) {
+                return step_result;
+            }
+        }
+    }
 
-#[derive(Default)]
-pub struct Cpu {
-    pub is_halting: bool,
-    pub cycle_counter: u64,
-    pub instructions: Vec<u8>,
-    pub registers: Registers,
-    pub memory: Memory,
-    pub type0_instruction_handler: Type0InstructionHandler,
-    pub type1_instruction_handler: Type1InstructionHandler,
-    pub type2_instruction_handler: Type2InstructionHandler,
-    pub type3_instruction_handler: Type3InstructionHandler,
-}
+    // Whether the CPU stopped, halted for good or locked up after step_result
+    pub fn has_finished(&mut self, step_result: &StepResult) -> bool {
+        // Only the joypad can wake a stopped CPU, and there is no joypad yet
+        match step_result.state {
+            CpuState::Running => false,
+            CpuState::Halted => {
+                self.bus.pending_interrupts() == 0 && !self.bus.may_request_interrupt()
+            }
+            CpuState::Stopped | CpuState::Locked => true,
+        }
+    }
 
-impl Debug for Cpu {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let instructions_str: String = self.instructions.iter().map(|&b| format!("{:08b}", b)).collect::<Vec<_>>().join("\n");
-        writeln!(
-            f,
-            "========== CPU ==========\n===== Instructions =====\n{}\n===== Registers =====\n{:?}\n===== Memory =====\n{:?}",
-            instructions_str, self.registers, self.memory
-        )
+    // The error that locked up the CPU, if any
+    pub fn get_lock_result(&self) -> Result<(), EmulatorError> {
+        match &self.lock_error {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    // Runs until the CPU has finished, a lock up being reported as an error
+    pub fn run(&mut self) -> Result<(), EmulatorError> {
+        self.run_until(|cpu, step_result| cpu.has_finished(step_result));
+        self.get_lock_result()
     }
 }
 
-impl Cpu {
-    pub fn handle_instruction(&mut self, instruction: u8) {
-        let op_type: u8 = instruction >> 6;
-        match op_type {
-            0b00 => self.type0_instruction_handler.handle_instruction(
-                instruction,
-                &mut self.cycle_counter,
-                &mut self.registers,
-            ),
-
-            0b01 => self.type1_instruction_handler.handle_instruction(
-                instruction,
-                &mut self.cycle_counter,
-                &mut self.registers,
-                &mut self.memory,
-                &mut self.is_halting,
-            ),
-
-            0b10 => self.type2_instruction_handler.handle_instruction(
-                instruction,
-                &mut self.cycle_counter,
-                &mut self.registers,
-                &self.memory,
-            ),
-
-            0b11 => self.type3_instruction_handler.handle_instruction(
-                instruction,
-                &mut self.cycle_counter,
-                &mut self.registers,
-                &mut self.memory,
-            ),
-            _ => panic!("Unknown operation type"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::FlatBus;
+
+    fn cpu_with_program(program: &[u8]) -> Cpu {
+        let mut cpu: Cpu = Cpu::default();
+        cpu.bus.load_program(0x0100, program);
+        cpu.registers.pc = 0x0100;
+        cpu.registers.sp = 0xFFFE;
+        cpu
+    }
+
+    // Executes the first instruction of program after set_up, returns the CPU and its M-cycles
+    fn step_program(program: &[u8], set_up: impl FnOnce(&mut Cpu)) -> (Cpu, u64) {
+        let mut cpu: Cpu = cpu_with_program(program);
+        set_up(&mut cpu);
+        let m_cycles: u64 = cpu.step().m_cycles;
+        (cpu, m_cycles)
+    }
+
+    const Z: u8 = Flags::Z as u8;
+    const N: u8 = Flags::N as u8;
+    const H: u8 = Flags::H as u8;
+    const C: u8 = Flags::C as u8;
+
+    #[test]
+    fn block_0_instructions_take_their_m_cycles() {
+        let cases: [(&[u8], u64); 12] = [
+            (&[0x00], 1),             // nop
+            (&[0x01, 0x34, 0x12], 3), // ld bc, $1234
+            (&[0x02], 2),             // ld [bc], a
+            (&[0x0A], 2),             // ld a, [bc]
+            (&[0x08, 0x00, 0xC0], 5), // ld [$c000], sp
+            (&[0x03], 2),             // inc bc
+            (&[0x09], 2),             // add hl, bc
+            (&[0x34], 3),             // inc [hl]
+            (&[0x36, 0x42], 3),       // ld [hl], $42
+            (&[0x3E, 0x42], 2),       // ld a, $42
+            (&[0x18, 0x02], 3),       // jr +2
+            (&[0x20, 0x02], 2),       // jr nz, +2 not taken
+        ];
+        for (program, expected_m_cycles) in cases {
+            let (_, m_cycles): (Cpu, u64) = step_program(program, |cpu| {
+                cpu.registers.set_r16_register_value(R16::Bc, 0xC000);
+                cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+                cpu.registers.f = Z;
+            });
+            assert_eq!(m_cycles, expected_m_cycles, "op code {:#04X}", program[0]);
         }
     }
 
-    pub fn run(&mut self) {
-        while self.registers.pc < self.instructions.len() as u16 {
-            if !self.is_halting {
-                let current_instruction: u8 = self.instructions[self.registers.pc as usize];
-                self.registers.pc += 1;
-                self.handle_instruction(current_instruction);
-            }
+    #[test]
+    fn daa_adjusts_after_additions_and_subtractions() {
+        // daa
+        let (cpu, m_cycles): (Cpu, u64) = step_program(&[0x27], |cpu| {
+            cpu.registers.a = 0x7D;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x83, 0));
+        assert_eq!(m_cycles, 1);
+
+        let (cpu, _): (Cpu, u64) = step_program(&[0x27], |cpu| {
+            cpu.registers.a = 0x9A;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x00, Z | C));
+
+        // After a subtraction only the digits that borrowed are adjusted
+        let (cpu, _): (Cpu, u64) = step_program(&[0x27], |cpu| {
+            cpu.registers.a = 0x4B;
+            cpu.registers.f = N | H;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x45, N));
+    }
+
+    #[test]
+    fn accumulator_rotates_always_reset_the_zero_flag() {
+        // rlca
+        let (cpu, m_cycles): (Cpu, u64) = step_program(&[0x07], |cpu| {
+            cpu.registers.a = 0x80;
+            cpu.registers.f = Z | N | H;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x01, C));
+        assert_eq!(m_cycles, 1);
+
+        // rra
+        let (cpu, _): (Cpu, u64) = step_program(&[0x1F], |cpu| {
+            cpu.registers.a = 0x01;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x00, C));
+
+        // rla
+        let (cpu, _): (Cpu, u64) = step_program(&[0x17], |cpu| {
+            cpu.registers.a = 0x00;
+            cpu.registers.f = C;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x01, 0));
+    }
+
+    #[test]
+    fn block_0_arithmetic_sets_its_flags() {
+        // add hl, bc keeps the zero flag and carries out of bit 11
+        let (cpu, _): (Cpu, u64) = step_program(&[0x09], |cpu| {
+            cpu.registers.set_r16_register_value(R16::Hl, 0x0FFF);
+            cpu.registers.set_r16_register_value(R16::Bc, 0x0001);
+            cpu.registers.f = Z | N;
+        });
+        assert_eq!(cpu.registers.get_hl_value(), 0x1000);
+        assert_eq!(cpu.registers.f, Z | H);
+
+        // inc a keeps the carry flag
+        let (cpu, _): (Cpu, u64) = step_program(&[0x3C], |cpu| {
+            cpu.registers.a = 0xFF;
+            cpu.registers.f = N | C;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x00, Z | H | C));
+
+        // dec a
+        let (cpu, _): (Cpu, u64) = step_program(&[0x3D], |cpu| {
+            cpu.registers.a = 0x10;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x0F, N | H));
+
+        // cpl, scf and ccf
+        let (cpu, _): (Cpu, u64) = step_program(&[0x2F], |cpu| {
+            cpu.registers.a = 0x35;
+            cpu.registers.f = Z | C;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0xCA, Z | N | H | C));
+        let (cpu, _): (Cpu, u64) = step_program(&[0x37], |cpu| cpu.registers.f = Z | N | H);
+        assert_eq!(cpu.registers.f, Z | C);
+        let (cpu, _): (Cpu, u64) = step_program(&[0x3F], |cpu| cpu.registers.f = N | H | C);
+        assert_eq!(cpu.registers.f, 0);
+    }
+
+    #[test]
+    fn prefixed_instructions_take_their_m_cycles() {
+        let cases: [(&[u8], u64); 6] = [
+            (&[0xCB, 0x00], 2), // rlc b
+            (&[0xCB, 0x06], 4), // rlc [hl]
+            (&[0xCB, 0x37], 2), // swap a
+            (&[0xCB, 0x7E], 3), // bit 7, [hl]
+            (&[0xCB, 0x86], 4), // res 0, [hl]
+            (&[0xCB, 0xC6], 4), // set 0, [hl]
+        ];
+        for (program, expected_m_cycles) in cases {
+            let (_, m_cycles): (Cpu, u64) = step_program(program, |cpu| {
+                cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+            });
+            assert_eq!(m_cycles, expected_m_cycles, "op code {:#04X}", program[1]);
         }
     }
+
+    #[test]
+    fn prefixed_shifts_set_the_zero_and_carry_flags() {
+        // rlc [hl]
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x06], |cpu| {
+            cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+            cpu.bus.set_value_at_memory_address(0xC000, 0x80);
+            cpu.registers.f = Z | N | H;
+        });
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xC000), 0x01);
+        assert_eq!(cpu.registers.f, C);
+
+        // Unlike rla, rl a sets the zero flag
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x17], |cpu| {
+            cpu.registers.a = 0x80;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x00, Z | C));
+
+        // sra b keeps bit 7
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x28], |cpu| {
+            cpu.registers.b = 0x81;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.b, cpu.registers.f), (0xC0, C));
+
+        // srl b clears it
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x38], |cpu| {
+            cpu.registers.b = 0x81;
+            cpu.registers.f = 0;
+        });
+        assert_eq!((cpu.registers.b, cpu.registers.f), (0x40, C));
+
+        // swap a resets the carry flag
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x37], |cpu| {
+            cpu.registers.a = 0x00;
+            cpu.registers.f = C;
+        });
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x00, Z));
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x37], |cpu| cpu.registers.a = 0xF1);
+        assert_eq!((cpu.registers.a, cpu.registers.f), (0x1F, 0));
+    }
+
+    #[test]
+    fn bit_tests_keep_the_carry_flag_and_res_set_keep_all_flags() {
+        // bit 7, [hl]
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x7E], |cpu| {
+            cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+            cpu.bus.set_value_at_memory_address(0xC000, 0x7F);
+            cpu.registers.f = N | C;
+        });
+        assert_eq!(cpu.registers.f, Z | H | C);
+
+        // bit 0, a
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0x47], |cpu| {
+            cpu.registers.a = 0x01;
+            cpu.registers.f = Z;
+        });
+        assert_eq!(cpu.registers.f, H);
+
+        // res 7, [hl] then set 0, [hl]
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0xBE], |cpu| {
+            cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+            cpu.bus.set_value_at_memory_address(0xC000, 0xF0);
+            cpu.registers.f = Z | N | H | C;
+        });
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xC000), 0x70);
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCB, 0xC6], |cpu| {
+            cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+            cpu.bus.set_value_at_memory_address(0xC000, 0x70);
+            cpu.registers.f = Z | N | H | C;
+        });
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xC000), 0x71);
+        assert_eq!(cpu.registers.f, Z | N | H | C);
+    }
+
+    #[test]
+    fn block_3_instructions_take_their_m_cycles() {
+        let cases: [(&[u8], u64); 20] = [
+            (&[0xC0], 2),             // ret nz not taken
+            (&[0xC8], 5),             // ret z taken
+            (&[0xC9], 4),             // ret
+            (&[0xD9], 4),             // reti
+            (&[0xC2, 0x00, 0x02], 3), // jp nz, $0200 not taken
+            (&[0xCA, 0x00, 0x02], 4), // jp z, $0200 taken
+            (&[0xE9], 1),             // jp hl
+            (&[0xC4, 0x00, 0x02], 3), // call nz, $0200 not taken
+            (&[0xCC, 0x00, 0x02], 6), // call z, $0200 taken
+            (&[0xC7], 4),             // rst $00
+            (&[0xC1], 3),             // pop bc
+            (&[0xC5], 4),             // push bc
+            (&[0xE0, 0x80], 3),       // ldh [$ff80], a
+            (&[0xF2], 2),             // ldh a, [c]
+            (&[0xEA, 0x00, 0xC0], 4), // ld [$c000], a
+            (&[0xFA, 0x00, 0xC0], 4), // ld a, [$c000]
+            (&[0xE8, 0x01], 4),       // add sp, 1
+            (&[0xF8, 0x01], 3),       // ld hl, sp + 1
+            (&[0xF9], 2),             // ld sp, hl
+            (&[0xC6, 0x01], 2),       // add a, 1
+        ];
+        for (program, expected_m_cycles) in cases {
+            let (_, m_cycles): (Cpu, u64) = step_program(program, |cpu| {
+                cpu.registers.set_r16_register_value(R16::Hl, 0xC000);
+                cpu.registers.sp = 0xDFF0;
+                cpu.registers.f = Z;
+            });
+            assert_eq!(m_cycles, expected_m_cycles, "op code {:#04X}", program[0]);
+        }
+    }
+
+    #[test]
+    fn sp_offsets_carry_from_the_low_byte() {
+        // add sp, 1
+        let (cpu, _): (Cpu, u64) = step_program(&[0xE8, 0x01], |cpu| {
+            cpu.registers.sp = 0x00FF;
+            cpu.registers.f = Z | N;
+        });
+        assert_eq!((cpu.registers.sp, cpu.registers.f), (0x0100, H | C));
+
+        // add sp, -1 only carries when the low byte does
+        let (cpu, _): (Cpu, u64) = step_program(&[0xE8, 0xFF], |cpu| {
+            cpu.registers.sp = 0x0000;
+            cpu.registers.f = Z | N | H | C;
+        });
+        assert_eq!((cpu.registers.sp, cpu.registers.f), (0xFFFF, 0));
+        let (cpu, _): (Cpu, u64) = step_program(&[0xE8, 0xFF], |cpu| cpu.registers.sp = 0x0001);
+        assert_eq!((cpu.registers.sp, cpu.registers.f), (0x0000, H | C));
+
+        // ld hl, sp - 2 sets the same flags and leaves sp alone
+        let (cpu, _): (Cpu, u64) = step_program(&[0xF8, 0xFE], |cpu| {
+            cpu.registers.sp = 0xFFF8;
+            cpu.registers.f = Z;
+        });
+        assert_eq!(cpu.registers.get_hl_value(), 0xFFF6);
+        assert_eq!((cpu.registers.sp, cpu.registers.f), (0xFFF8, H | C));
+    }
+
+    #[test]
+    fn ldh_addresses_are_offsets_from_0xff00() {
+        // ldh [$80], a, ldh a, [c]
+        let (mut cpu, _): (Cpu, u64) = step_program(&[0xE0, 0x80, 0xF2], |cpu| {
+            cpu.registers.a = 0x42;
+            cpu.registers.c = 0x80;
+        });
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xFF80), 0x42);
+        cpu.registers.a = 0x00;
+        cpu.step();
+        assert_eq!(cpu.registers.a, 0x42);
+    }
+
+    #[test]
+    fn call_and_reti_go_through_the_stack() {
+        // call $0200
+        let (cpu, _): (Cpu, u64) = step_program(&[0xCD, 0x00, 0x02], |_| {});
+        assert_eq!(cpu.registers.pc, 0x0200);
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xFFFD), 0x01);
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xFFFC), 0x03);
+
+        // reti returns and enables interrupts right away
+        let (cpu, _): (Cpu, u64) = step_program(&[0xD9], |cpu| {
+            cpu.registers.sp = 0xFFFC;
+            cpu.bus.set_value_at_memory_address(0xFFFC, 0x03);
+            cpu.bus.set_value_at_memory_address(0xFFFD, 0x01);
+        });
+        assert_eq!(cpu.registers.pc, 0x0103);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert!(cpu.interrupt_master_enable);
+
+        // di
+        let (cpu, _): (Cpu, u64) = step_program(&[0xF3], |cpu| cpu.interrupt_master_enable = true);
+        assert!(!cpu.interrupt_master_enable);
+    }
+
+    #[test]
+    fn step_reports_the_executed_instruction_and_its_cycles() {
+        // call $0110
+        let mut cpu: Cpu = cpu_with_program(&[0xCD, 0x10, 0x01]);
+
+        let step_result: StepResult = cpu.step();
+        assert_eq!(step_result.pc, 0x0100);
+        assert_eq!(
+            step_result.kind,
+            StepKind::Instruction {
+                op_code: 0xCD,
+                instruction: Instruction::Call {
+                    cond: None,
+                    address: 0x0110
+                }
+            }
+        );
+        assert_eq!(step_result.m_cycles, 6);
+        assert_eq!(step_result.t_cycles(), 24);
+        assert_eq!(step_result.state, CpuState::Running);
+    }
+
+    #[test]
+    fn flat_bus_is_ticked_once_per_m_cycle() {
+        // ld hl, $c000; ld [hl], $42; push hl; stop
+        let mut cpu: Cpu<FlatBus> = Cpu::default();
+        cpu.bus.memory[0x0000..0x0008]
+            .copy_from_slice(&[0x21, 0x00, 0xC0, 0x36, 0x42, 0xE5, 0x10, 0x00]);
+        cpu.registers.sp = 0xFFFE;
+
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.bus.memory[0xC000], 0x42);
+        assert_eq!(cpu.bus.memory[0xFFFD], 0xC0);
+        assert_eq!(cpu.bus.memory[0xFFFC], 0x00);
+        assert_eq!(cpu.bus.ticks, 3 + 3 + 4 + 2);
+        assert_eq!(cpu.bus.ticks, cpu.cycle_counter);
+    }
+
+    #[test]
+    fn run_for_cycles_and_run_until_stop_on_their_condition() {
+        // nop, nop, nop, inc a, stop
+        let mut cpu: Cpu = cpu_with_program(&[0x00, 0x00, 0x00, 0x3C, 0x10, 0x00]);
+
+        assert_eq!(cpu.run_for_cycles(2), 2);
+        assert_eq!(cpu.registers.pc, 0x0102);
+
+        let step_result: StepResult = cpu.run_until(|cpu, _| cpu.registers.a == 1);
+        assert_eq!(step_result.pc, 0x0103);
+
+        let step_result: StepResult =
+            cpu.run_until(|_, step_result| step_result.state == CpuState::Stopped);
+        assert_eq!(step_result.pc, 0x0104);
+        assert_eq!(cpu.step().kind, StepKind::Idle);
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_the_next_instruction() {
+        // ei, nop, nop
+        let mut cpu: Cpu = cpu_with_program(&[0xFB, 0x00, 0x00]);
+        cpu.bus.interrupts.interrupt_enable = Interrupt::Timer.bit();
+        cpu.bus.interrupts.request(Interrupt::Timer);
+
+        assert_eq!(cpu.step().pc, 0x0100);
+        assert_eq!(cpu.step().pc, 0x0101);
+
+        let step_result: StepResult = cpu.step();
+        assert_eq!(
+            step_result.kind,
+            StepKind::Interrupt {
+                handler_address: 0x0050
+            }
+        );
+        assert_eq!(step_result.m_cycles, 5);
+        assert_eq!(cpu.registers.pc, 0x0050);
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xFFFC), 0x02);
+        assert_eq!(cpu.bus.get_value_at_memory_address(0xFFFD), 0x01);
+        assert!(!cpu.interrupt_master_enable);
+        assert_eq!(cpu.bus.interrupts.interrupt_flag, 0);
+    }
+
+    #[test]
+    fn di_right_after_ei_keeps_interrupts_disabled() {
+        // ei, di, nop
+        let mut cpu: Cpu = cpu_with_program(&[0xFB, 0xF3, 0x00]);
+        cpu.run_for_cycles(3);
+        assert!(!cpu.interrupt_master_enable);
+    }
+
+    #[test]
+    fn dispatches_the_highest_priority_interrupt_first() {
+        let mut cpu: Cpu = cpu_with_program(&[0x00]);
+        cpu.interrupt_master_enable = true;
+        cpu.bus.interrupts.interrupt_enable = 0b00011111;
+        cpu.bus.interrupts.request(Interrupt::Joypad);
+        cpu.bus.interrupts.request(Interrupt::Stat);
+
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0048);
+        assert_eq!(cpu.bus.interrupts.interrupt_flag, Interrupt::Joypad.bit());
+    }
+
+    #[test]
+    fn halt_exits_on_pending_interrupt_without_ime() {
+        // halt, inc a
+        let mut cpu: Cpu = cpu_with_program(&[0x76, 0x3C]);
+        cpu.bus.interrupts.interrupt_enable = Interrupt::VBlank.bit();
+        assert_eq!(cpu.step().state, CpuState::Halted);
+        assert_eq!(cpu.step().kind, StepKind::Idle);
+
+        cpu.bus.interrupts.request(Interrupt::VBlank);
+        let step_result: StepResult = cpu.step();
+        assert_eq!(step_result.pc, 0x0101);
+        assert_eq!(step_result.state, CpuState::Running);
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn timer_interrupt_wakes_a_halted_cpu_during_run() {
+        // ld a, 0x05, ldh (TAC), a, ld a, 0x04, ldh (IE), a, halt, inc a, stop
+        let mut cpu: Cpu = cpu_with_program(&[
+            0x3E, 0x05, 0xE0, 0x07, 0x3E, 0x04, 0xE0, 0xFF, 0x76, 0x3C, 0x10, 0x00,
+        ]);
+        let step_result: StepResult =
+            cpu.run_until(|_, step_result| step_result.state == CpuState::Halted);
+        assert_eq!(step_result.pc, 0x0108);
+
+        // The halted CPU waits for the overflow and the reload in one step each
+        let step_result: StepResult = cpu.step();
+        assert!(step_result.m_cycles > 1);
+        assert_eq!(cpu.step().m_cycles, 1);
+        assert!(cpu.run().is_ok());
+        assert_eq!(cpu.state, CpuState::Stopped);
+        assert_eq!(cpu.registers.a, 0x05);
+        // 256 TIMA increments every 4 M-cycles
+        assert!(cpu.cycle_counter > 256 * 4);
+        assert_eq!(cpu.bus.interrupts.interrupt_flag, Interrupt::Timer.bit());
+    }
+
+    #[test]
+    fn halt_bug_reads_the_next_op_code_twice() {
+        // halt, inc a
+        let mut cpu: Cpu = cpu_with_program(&[0x76, 0x3C]);
+        cpu.bus.interrupts.interrupt_enable = Interrupt::Serial.bit();
+        cpu.bus.interrupts.request(Interrupt::Serial);
+
+        assert_eq!(cpu.step().state, CpuState::Running);
+        assert_eq!(cpu.step().pc, 0x0101);
+        assert_eq!(cpu.step().pc, 0x0101);
+        assert_eq!(cpu.registers.a, 2);
+        assert_eq!(cpu.registers.pc, 0x0102);
+    }
+
+    #[test]
+    fn illegal_op_code_locks_the_cpu_instead_of_panicking() {
+        // ei, nop, $D3
+        let mut cpu: Cpu = cpu_with_program(&[0xFB, 0x00, 0xD3]);
+        cpu.bus.interrupts.interrupt_enable = Interrupt::VBlank.bit();
+
+        assert_eq!(
+            cpu.run(),
+            Err(EmulatorError::IllegalOpCode {
+                op_code: 0xD3,
+                address: 0x0102
+            })
+        );
+        assert_eq!(cpu.state, CpuState::Locked);
+
+        // Interrupts can not wake a locked CPU
+        cpu.bus.interrupts.request(Interrupt::VBlank);
+        let step_result: StepResult = cpu.step();
+        assert_eq!(step_result.kind, StepKind::Idle);
+        assert_eq!(step_result.state, CpuState::Locked);
+        assert_eq!(cpu.registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn post_boot_state_matches_each_model() {
+        let dmg: Cpu = Cpu::post_boot(Model::Dmg, Memory::default());
+        assert_eq!(
+            dmg.registers.get_r16_register_stack_value(R16Stk::Af),
+            0x01B0
+        );
+        assert_eq!(dmg.registers.get_r16_register_value(R16::Bc), 0x0013);
+        assert_eq!(dmg.registers.get_r16_register_value(R16::De), 0x00D8);
+        assert_eq!(dmg.registers.get_r16_register_value(R16::Hl), 0x014D);
+        assert_eq!(dmg.registers.sp, 0xFFFE);
+        assert_eq!(dmg.registers.pc, 0x0100);
+        assert_eq!(dmg.bus.get_value_at_memory_address(0xFF40), 0x91);
+        assert_eq!(dmg.bus.get_value_at_memory_address(0xFF0F), 0xE1);
+
+        let mut memory: Memory = Memory::new(Model::Mgb);
+        memory.load_program(0x014D, &[0x66]);
+        let mgb: Cpu = Cpu::post_boot(Model::Mgb, memory);
+        assert_eq!(
+            mgb.registers.get_r16_register_stack_value(R16Stk::Af),
+            0xFFB0
+        );
+
+        let mut memory: Memory = Memory::new(Model::Cgb);
+        memory.load_program(0x0143, &[0x80]);
+        let cgb: Cpu = Cpu::post_boot(Model::Cgb, memory);
+        assert_eq!(
+            cgb.registers.get_r16_register_stack_value(R16Stk::Af),
+            0x1180
+        );
+        assert_eq!(cgb.registers.get_r16_register_value(R16::De), 0xFF56);
+        assert_eq!(cgb.bus.get_value_at_memory_address(0xFF70), 0xF8);
+
+        let mut memory: Memory = Memory::new(Model::Agb);
+        memory.load_program(0x0143, &[0x00]);
+        let agb: Cpu = Cpu::post_boot(Model::Agb, memory);
+        assert_eq!(
+            agb.registers.get_r16_register_stack_value(R16Stk::Af),
+            0x1100
+        );
+        assert_eq!(agb.registers.get_r16_register_value(R16::Bc), 0x0100);
+        assert_eq!(agb.registers.get_r16_register_value(R16::Hl), 0x007C);
+
+        let sgb2: Cpu = Cpu::post_boot(Model::Sgb2, Memory::new(Model::Sgb2));
+        assert_eq!(
+            sgb2.registers.get_r16_register_stack_value(R16Stk::Af),
+            0xFF00
+        );
+        assert_eq!(sgb2.bus.get_value_at_memory_address(0xFF26), 0xF0);
+    }
 }
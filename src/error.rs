@@ -0,0 +1,73 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    // One of the 11 unused op codes was executed, real hardware hard locks until reset
+    IllegalOpCode { op_code: u8, address: u16 },
+    // Boot ROMs are 256 bytes for DMG models and 2304 bytes for CGB models
+    InvalidBootRomSize { size: usize },
+    Io { path: String, message: String },
+    // The ROM ends before the end of the cartridge header at 0x014F
+    CartridgeTooSmall { size: usize },
+    // The boot ROM refuses to start cartridges with a wrong header checksum
+    HeaderChecksumMismatch { expected: u8, computed: u8 },
+    UnknownCartridgeType { code: u8 },
+    UnknownRomSize { code: u8 },
+    UnknownRamSize { code: u8 },
+    // The ROM size declared in the header does not match the file
+    RomSizeMismatch { expected: usize, actual: usize },
+    // The cartridge type is known but its mapper is not emulated
+    UnsupportedMapper { code: u8 },
+    // A save file must hold exactly the cartridge RAM
+    InvalidSaveSize { expected: usize, actual: usize },
+    WriteFailed { path: String, message: String },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::IllegalOpCode { op_code, address } => write!(
+                f,
+                "CPU locked up on illegal op code ${op_code:02X} at ${address:04X}"
+            ),
+            EmulatorError::InvalidBootRomSize { size } => write!(
+                f,
+                "Invalid boot ROM size of {size} bytes, expected 256 (DMG) or 2304 (CGB)"
+            ),
+            EmulatorError::Io { path, message } => write!(f, "Could not read {path}: {message}"),
+            EmulatorError::CartridgeTooSmall { size } => write!(
+                f,
+                "Cartridge of {size} bytes is too small to hold a header, expected at least 336"
+            ),
+            EmulatorError::HeaderChecksumMismatch { expected, computed } => write!(
+                f,
+                "Header checksum mismatch, header says ${expected:02X} but computed ${computed:02X}"
+            ),
+            EmulatorError::UnknownCartridgeType { code } => {
+                write!(f, "Unknown cartridge type ${code:02X}")
+            }
+            EmulatorError::UnknownRomSize { code } => {
+                write!(f, "Unknown ROM size code ${code:02X}")
+            }
+            EmulatorError::UnknownRamSize { code } => {
+                write!(f, "Unknown RAM size code ${code:02X}")
+            }
+            EmulatorError::RomSizeMismatch { expected, actual } => write!(
+                f,
+                "ROM size mismatch, header says {expected} bytes but the file holds {actual} bytes"
+            ),
+            EmulatorError::UnsupportedMapper { code } => {
+                write!(f, "Unsupported mapper for cartridge type ${code:02X}")
+            }
+            EmulatorError::InvalidSaveSize { expected, actual } => write!(
+                f,
+                "Invalid save size of {actual} bytes, the cartridge RAM holds {expected} bytes"
+            ),
+            EmulatorError::WriteFailed { path, message } => {
+                write!(f, "Could not write {path}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
@@ -0,0 +1,632 @@
+// Instruction decoding, operand encodings are based on https://gbdev.io/pandocs/CPU_Instruction_Set.html
+// The decoded Instruction is shared by the execution, the disassembly and the tests
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum R8 {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HlMem,
+    A,
+}
+
+impl R8 {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => R8::B,
+            0b001 => R8::C,
+            0b010 => R8::D,
+            0b011 => R8::E,
+            0b100 => R8::H,
+            0b101 => R8::L,
+            0b110 => R8::HlMem,
+            _ => R8::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum R16 {
+    Bc,
+    De,
+    Hl,
+    Sp,
+}
+
+impl R16 {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => R16::Bc,
+            0b01 => R16::De,
+            0b10 => R16::Hl,
+            _ => R16::Sp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum R16Stk {
+    Bc,
+    De,
+    Hl,
+    Af,
+}
+
+impl R16Stk {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => R16Stk::Bc,
+            0b01 => R16Stk::De,
+            0b10 => R16Stk::Hl,
+            _ => R16Stk::Af,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum R16Mem {
+    Bc,
+    De,
+    HlInc,
+    HlDec,
+}
+
+impl R16Mem {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => R16Mem::Bc,
+            0b01 => R16Mem::De,
+            0b10 => R16Mem::HlInc,
+            _ => R16Mem::HlDec,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Nz,
+    Z,
+    Nc,
+    C,
+}
+
+impl Cond {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Cond::Nz,
+            0b01 => Cond::Z,
+            0b10 => Cond::Nc,
+            _ => Cond::C,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
+
+impl AluOp {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => AluOp::Add,
+            0b001 => AluOp::Adc,
+            0b010 => AluOp::Sub,
+            0b011 => AluOp::Sbc,
+            0b100 => AluOp::And,
+            0b101 => AluOp::Xor,
+            0b110 => AluOp::Or,
+            _ => AluOp::Cp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOperand {
+    R8(R8),
+    Imm8(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+}
+
+impl ShiftOp {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => ShiftOp::Rlc,
+            0b001 => ShiftOp::Rrc,
+            0b010 => ShiftOp::Rl,
+            0b011 => ShiftOp::Rr,
+            0b100 => ShiftOp::Sla,
+            0b101 => ShiftOp::Sra,
+            0b110 => ShiftOp::Swap,
+            _ => ShiftOp::Srl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // Block 0
+    Nop,
+    LdR16Imm16 { dst: R16, value: u16 },
+    LdR16MemA { dst: R16Mem },
+    LdAR16Mem { src: R16Mem },
+    LdImm16Sp { address: u16 },
+    IncR16(R16),
+    DecR16(R16),
+    AddHlR16(R16),
+    IncR8(R8),
+    DecR8(R8),
+    LdR8Imm8 { dst: R8, value: u8 },
+    Rlca,
+    Rrca,
+    Rla,
+    Rra,
+    Daa,
+    Cpl,
+    Scf,
+    Ccf,
+    Jr { cond: Option<Cond>, offset: i8 },
+    Stop,
+
+    // Block 1
+    Ld8 { dst: R8, src: R8 },
+    Halt,
+
+    // Block 2 and the imm8 operations of block 3
+    Alu { op: AluOp, operand: AluOperand },
+
+    // Block 3
+    Ret { cond: Option<Cond> },
+    Reti,
+    Jp { cond: Option<Cond>, address: u16 },
+    // Never conditional, unlike the jump to an immediate address
+    JpHl,
+    Call { cond: Option<Cond>, address: u16 },
+    Rst { target: u8 },
+    Pop(R16Stk),
+    Push(R16Stk),
+    LdhCA,
+    LdhImm8A { offset: u8 },
+    LdImm16A { address: u16 },
+    LdhAC,
+    LdhAImm8 { offset: u8 },
+    LdAImm16 { address: u16 },
+    AddSpImm8 { offset: i8 },
+    LdHlSpImm8 { offset: i8 },
+    LdSpHl,
+    Di,
+    Ei,
+
+    // $CB prefix
+    Shift { op: ShiftOp, operand: R8 },
+    Bit { bit: u8, operand: R8 },
+    Res { bit: u8, operand: R8 },
+    Set { bit: u8, operand: R8 },
+
+    // Opcodes that hard lock the CPU
+    Illegal(u8),
+}
+
+// Number of bytes, op code included, used by the instruction starting with op_code
+pub fn instruction_length(op_code: u8) -> usize {
+    match op_code {
+        0b00000001 | 0b00010001 | 0b00100001 | 0b00110001 => 3, // ld r16, imm16
+        0b00001000 => 3,                                        // ld [imm16], sp
+        0b11000010 | 0b11001010 | 0b11010010 | 0b11011010 => 3, // jp cond, imm16
+        0b11000011 => 3,                                        // jp imm16
+        0b11000100 | 0b11001100 | 0b11010100 | 0b11011100 => 3, // call cond, imm16
+        0b11001101 => 3,                                        // call imm16
+        0b11101010 | 0b11111010 => 3,                           // ld [imm16], a / ld a, [imm16]
+        0b00000110 | 0b00001110 | 0b00010110 | 0b00011110 => 2, // ld r8, imm8
+        0b00100110 | 0b00101110 | 0b00110110 | 0b00111110 => 2, // ld r8, imm8
+        0b00010000 => 2,                                        // stop
+        0b00011000 | 0b00100000 | 0b00101000 | 0b00110000 | 0b00111000 => 2, // jr
+        0b11000110 | 0b11001110 | 0b11010110 | 0b11011110 => 2, // alu a, imm8
+        0b11100110 | 0b11101110 | 0b11110110 | 0b11111110 => 2, // alu a, imm8
+        0b11100000 | 0b11110000 => 2,                           // ldh [imm8], a / ldh a, [imm8]
+        0b11101000 | 0b11111000 => 2,                           // add sp, imm8 / ld hl, sp + imm8
+        0b11001011 => 2,                                        // prefix
+        _ => 1,
+    }
+}
+
+// Decodes the instruction starting at bytes[0], bytes must hold at least instruction_length(bytes[0]) bytes
+pub fn decode(bytes: &[u8]) -> Instruction {
+    let instruction: u8 = bytes[0];
+    let imm8: u8 = bytes.get(1).copied().unwrap_or(0);
+    let imm16: u16 = ((bytes.get(2).copied().unwrap_or(0) as u16) << 8) | imm8 as u16;
+
+    match instruction >> 6 {
+        0b00 => decode_block0(instruction, imm8, imm16),
+        0b01 => {
+            if instruction == 0b01110110 {
+                Instruction::Halt
+            } else {
+                Instruction::Ld8 {
+                    dst: R8::from_bits(instruction >> 3),
+                    src: R8::from_bits(instruction),
+                }
+            }
+        }
+        0b10 => Instruction::Alu {
+            op: AluOp::from_bits(instruction >> 3),
+            operand: AluOperand::R8(R8::from_bits(instruction)),
+        },
+        _ => decode_block3(instruction, imm8, imm16),
+    }
+}
+
+fn decode_block0(instruction: u8, imm8: u8, imm16: u16) -> Instruction {
+    let r16: R16 = R16::from_bits(instruction >> 4);
+    let r16_mem: R16Mem = R16Mem::from_bits(instruction >> 4);
+    let r8: R8 = R8::from_bits(instruction >> 3);
+
+    match instruction {
+        0b00000000 => Instruction::Nop,
+        0b00001000 => Instruction::LdImm16Sp { address: imm16 },
+        0b00010000 => Instruction::Stop,
+        0b00011000 => Instruction::Jr {
+            cond: None,
+            offset: imm8 as i8,
+        },
+        0b00000111 => Instruction::Rlca,
+        0b00001111 => Instruction::Rrca,
+        0b00010111 => Instruction::Rla,
+        0b00011111 => Instruction::Rra,
+        0b00100111 => Instruction::Daa,
+        0b00101111 => Instruction::Cpl,
+        0b00110111 => Instruction::Scf,
+        0b00111111 => Instruction::Ccf,
+        _ if instruction & 0b11100111 == 0b00100000 => Instruction::Jr {
+            cond: Some(Cond::from_bits(instruction >> 3)),
+            offset: imm8 as i8,
+        },
+        _ => match instruction & 0b00001111 {
+            0b0001 => Instruction::LdR16Imm16 {
+                dst: r16,
+                value: imm16,
+            },
+            0b0010 => Instruction::LdR16MemA { dst: r16_mem },
+            0b1010 => Instruction::LdAR16Mem { src: r16_mem },
+            0b0011 => Instruction::IncR16(r16),
+            0b1011 => Instruction::DecR16(r16),
+            0b1001 => Instruction::AddHlR16(r16),
+            _ => match instruction & 0b00000111 {
+                0b100 => Instruction::IncR8(r8),
+                0b101 => Instruction::DecR8(r8),
+                _ => Instruction::LdR8Imm8 {
+                    dst: r8,
+                    value: imm8,
+                },
+            },
+        },
+    }
+}
+
+fn decode_block3(instruction: u8, imm8: u8, imm16: u16) -> Instruction {
+    let cond: Cond = Cond::from_bits(instruction >> 3);
+    let r16_stk: R16Stk = R16Stk::from_bits(instruction >> 4);
+
+    match instruction {
+        0b11001001 => Instruction::Ret { cond: None },
+        0b11011001 => Instruction::Reti,
+        0b11000011 => Instruction::Jp {
+            cond: None,
+            address: imm16,
+        },
+        0b11101001 => Instruction::JpHl,
+        0b11001101 => Instruction::Call {
+            cond: None,
+            address: imm16,
+        },
+        0b11001011 => decode_prefixed(imm8),
+        0b11100010 => Instruction::LdhCA,
+        0b11100000 => Instruction::LdhImm8A { offset: imm8 },
+        0b11101010 => Instruction::LdImm16A { address: imm16 },
+        0b11110010 => Instruction::LdhAC,
+        0b11110000 => Instruction::LdhAImm8 { offset: imm8 },
+        0b11111010 => Instruction::LdAImm16 { address: imm16 },
+        0b11101000 => Instruction::AddSpImm8 { offset: imm8 as i8 },
+        0b11111000 => Instruction::LdHlSpImm8 { offset: imm8 as i8 },
+        0b11111001 => Instruction::LdSpHl,
+        0b11110011 => Instruction::Di,
+        0b11111011 => Instruction::Ei,
+        0b11010011 | 0b11011011 | 0b11011101 | 0b11100011 | 0b11100100 | 0b11101011
+        | 0b11101100 | 0b11101101 | 0b11110100 | 0b11111100 | 0b11111101 => {
+            Instruction::Illegal(instruction)
+        }
+        _ if instruction & 0b11000111 == 0b11000110 => Instruction::Alu {
+            op: AluOp::from_bits(instruction >> 3),
+            operand: AluOperand::Imm8(imm8),
+        },
+        _ if instruction & 0b11100111 == 0b11000000 => Instruction::Ret { cond: Some(cond) },
+        _ if instruction & 0b11100111 == 0b11000010 => Instruction::Jp {
+            cond: Some(cond),
+            address: imm16,
+        },
+        _ if instruction & 0b11100111 == 0b11000100 => Instruction::Call {
+            cond: Some(cond),
+            address: imm16,
+        },
+        _ if instruction & 0b11000111 == 0b11000111 => Instruction::Rst {
+            target: instruction & 0b00111000,
+        },
+        _ if instruction & 0b11001111 == 0b11000001 => Instruction::Pop(r16_stk),
+        _ => Instruction::Push(r16_stk),
+    }
+}
+
+fn decode_prefixed(instruction: u8) -> Instruction {
+    let bit: u8 = (instruction & 0b00111000) >> 3;
+    let operand: R8 = R8::from_bits(instruction);
+
+    match instruction >> 6 {
+        0b00 => Instruction::Shift {
+            op: ShiftOp::from_bits(bit),
+            operand,
+        },
+        0b01 => Instruction::Bit { bit, operand },
+        0b10 => Instruction::Res { bit, operand },
+        _ => Instruction::Set { bit, operand },
+    }
+}
+
+impl fmt::Display for R8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            R8::B => "b",
+            R8::C => "c",
+            R8::D => "d",
+            R8::E => "e",
+            R8::H => "h",
+            R8::L => "l",
+            R8::HlMem => "[hl]",
+            R8::A => "a",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for R16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            R16::Bc => "bc",
+            R16::De => "de",
+            R16::Hl => "hl",
+            R16::Sp => "sp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for R16Stk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            R16Stk::Bc => "bc",
+            R16Stk::De => "de",
+            R16Stk::Hl => "hl",
+            R16Stk::Af => "af",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for R16Mem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            R16Mem::Bc => "[bc]",
+            R16Mem::De => "[de]",
+            R16Mem::HlInc => "[hl+]",
+            R16Mem::HlDec => "[hl-]",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            Cond::Nz => "nz",
+            Cond::Z => "z",
+            Cond::Nc => "nc",
+            Cond::C => "c",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for AluOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            AluOp::Add => "add",
+            AluOp::Adc => "adc",
+            AluOp::Sub => "sub",
+            AluOp::Sbc => "sbc",
+            AluOp::And => "and",
+            AluOp::Xor => "xor",
+            AluOp::Or => "or",
+            AluOp::Cp => "cp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for AluOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AluOperand::R8(register) => write!(f, "{register}"),
+            AluOperand::Imm8(value) => write!(f, "${value:02x}"),
+        }
+    }
+}
+
+impl fmt::Display for ShiftOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            ShiftOp::Rlc => "rlc",
+            ShiftOp::Rrc => "rrc",
+            ShiftOp::Rl => "rl",
+            ShiftOp::Rr => "rr",
+            ShiftOp::Sla => "sla",
+            ShiftOp::Sra => "sra",
+            ShiftOp::Swap => "swap",
+            ShiftOp::Srl => "srl",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Nop => write!(f, "nop"),
+            Instruction::LdR16Imm16 { dst, value } => write!(f, "ld {dst}, ${value:04x}"),
+            Instruction::LdR16MemA { dst } => write!(f, "ld {dst}, a"),
+            Instruction::LdAR16Mem { src } => write!(f, "ld a, {src}"),
+            Instruction::LdImm16Sp { address } => write!(f, "ld [${address:04x}], sp"),
+            Instruction::IncR16(register) => write!(f, "inc {register}"),
+            Instruction::DecR16(register) => write!(f, "dec {register}"),
+            Instruction::AddHlR16(register) => write!(f, "add hl, {register}"),
+            Instruction::IncR8(register) => write!(f, "inc {register}"),
+            Instruction::DecR8(register) => write!(f, "dec {register}"),
+            Instruction::LdR8Imm8 { dst, value } => write!(f, "ld {dst}, ${value:02x}"),
+            Instruction::Rlca => write!(f, "rlca"),
+            Instruction::Rrca => write!(f, "rrca"),
+            Instruction::Rla => write!(f, "rla"),
+            Instruction::Rra => write!(f, "rra"),
+            Instruction::Daa => write!(f, "daa"),
+            Instruction::Cpl => write!(f, "cpl"),
+            Instruction::Scf => write!(f, "scf"),
+            Instruction::Ccf => write!(f, "ccf"),
+            Instruction::Jr { cond: None, offset } => write!(f, "jr {offset}"),
+            Instruction::Jr {
+                cond: Some(cond),
+                offset,
+            } => write!(f, "jr {cond}, {offset}"),
+            Instruction::Stop => write!(f, "stop"),
+            Instruction::Ld8 { dst, src } => write!(f, "ld {dst}, {src}"),
+            Instruction::Halt => write!(f, "halt"),
+            Instruction::Alu { op, operand } => write!(f, "{op} a, {operand}"),
+            Instruction::Ret { cond: None } => write!(f, "ret"),
+            Instruction::Ret { cond: Some(cond) } => write!(f, "ret {cond}"),
+            Instruction::Reti => write!(f, "reti"),
+            Instruction::Jp {
+                cond: None,
+                address,
+            } => write!(f, "jp ${address:04x}"),
+            Instruction::Jp {
+                cond: Some(cond),
+                address,
+            } => write!(f, "jp {cond}, ${address:04x}"),
+            Instruction::JpHl => write!(f, "jp hl"),
+            Instruction::Call {
+                cond: None,
+                address,
+            } => write!(f, "call ${address:04x}"),
+            Instruction::Call {
+                cond: Some(cond),
+                address,
+            } => write!(f, "call {cond}, ${address:04x}"),
+            Instruction::Rst { target } => write!(f, "rst ${target:02x}"),
+            Instruction::Pop(register) => write!(f, "pop {register}"),
+            Instruction::Push(register) => write!(f, "push {register}"),
+            Instruction::LdhCA => write!(f, "ldh [c], a"),
+            Instruction::LdhImm8A { offset } => write!(f, "ldh [${offset:02x}], a"),
+            Instruction::LdImm16A { address } => write!(f, "ld [${address:04x}], a"),
+            Instruction::LdhAC => write!(f, "ldh a, [c]"),
+            Instruction::LdhAImm8 { offset } => write!(f, "ldh a, [${offset:02x}]"),
+            Instruction::LdAImm16 { address } => write!(f, "ld a, [${address:04x}]"),
+            Instruction::AddSpImm8 { offset } => write!(f, "add sp, {offset}"),
+            Instruction::LdHlSpImm8 { offset } => write!(f, "ld hl, sp{offset:+}"),
+            Instruction::LdSpHl => write!(f, "ld sp, hl"),
+            Instruction::Di => write!(f, "di"),
+            Instruction::Ei => write!(f, "ei"),
+            Instruction::Shift { op, operand } => write!(f, "{op} {operand}"),
+            Instruction::Bit { bit, operand } => write!(f, "bit {bit}, {operand}"),
+            Instruction::Res { bit, operand } => write!(f, "res {bit}, {operand}"),
+            Instruction::Set { bit, operand } => write!(f, "set {bit}, {operand}"),
+            Instruction::Illegal(op_code) => write!(f, "illegal ${op_code:02x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_operands_from_their_encoding() {
+        assert_eq!(
+            decode(&[0b01000110]),
+            Instruction::Ld8 {
+                dst: R8::B,
+                src: R8::HlMem
+            }
+        );
+        assert_eq!(
+            decode(&[0b11001010, 0x34, 0x12]),
+            Instruction::Jp {
+                cond: Some(Cond::Z),
+                address: 0x1234
+            }
+        );
+        assert_eq!(decode(&[0b11101001]), Instruction::JpHl);
+        assert_eq!(
+            decode(&[0b00111010]),
+            Instruction::LdAR16Mem { src: R16Mem::HlDec }
+        );
+        assert_eq!(decode(&[0b11110101]), Instruction::Push(R16Stk::Af));
+        assert_eq!(decode(&[0b11111111]), Instruction::Rst { target: 0x38 });
+        assert_eq!(
+            decode(&[0b11001011, 0b01111110]),
+            Instruction::Bit {
+                bit: 7,
+                operand: R8::HlMem
+            }
+        );
+    }
+
+    #[test]
+    fn only_the_eleven_illegal_op_codes_decode_as_illegal() {
+        let illegal_op_codes: Vec<u8> = (0..=255u8)
+            .filter(|&op_code| matches!(decode(&[op_code, 0, 0]), Instruction::Illegal(_)))
+            .collect();
+        assert_eq!(
+            illegal_op_codes,
+            [
+                0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD
+            ]
+        );
+    }
+
+    #[test]
+    fn disassembles_in_pandocs_syntax() {
+        assert_eq!(decode(&[0x21, 0x00, 0xC0]).to_string(), "ld hl, $c000");
+        assert_eq!(decode(&[0x20, 0xFE]).to_string(), "jr nz, -2");
+        assert_eq!(decode(&[0xE0, 0x40]).to_string(), "ldh [$40], a");
+        assert_eq!(decode(&[0xF8, 0x05]).to_string(), "ld hl, sp+5");
+        assert_eq!(decode(&[0xCB, 0x37]).to_string(), "swap a");
+    }
+}
@@ -0,0 +1,85 @@
+// Interrupt registers and priorities are based on https://gbdev.io/pandocs/Interrupts.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // Ordered by priority, VBlank being the highest
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    // Bit of the interrupt in IE and IF
+    pub fn bit(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b00000001,
+            Interrupt::Stat => 0b00000010,
+            Interrupt::Timer => 0b00000100,
+            Interrupt::Serial => 0b00001000,
+            Interrupt::Joypad => 0b00010000,
+        }
+    }
+
+    // Highest priority interrupt among the pending bits
+    pub fn from_pending(pending: u8) -> Option<Interrupt> {
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.bit() != 0)
+    }
+
+    pub fn handler_address(self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x0040,
+            Interrupt::Stat => 0x0048,
+            Interrupt::Timer => 0x0050,
+            Interrupt::Serial => 0x0058,
+            Interrupt::Joypad => 0x0060,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InterruptController {
+    pub interrupt_enable: u8, // IE at 0xFFFF
+    pub interrupt_flag: u8,   // IF at 0xFF0F
+}
+
+impl InterruptController {
+    pub const INTERRUPT_FLAG_ADDRESS: u16 = 0xFF0F;
+    pub const INTERRUPT_ENABLE_ADDRESS: u16 = 0xFFFF;
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.bit()
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag &= !interrupt.bit()
+    }
+
+    // Interrupts both enabled and requested, regardless of IME
+    pub fn pending(&self) -> u8 {
+        self.interrupt_enable & self.interrupt_flag & 0b00011111
+    }
+
+    pub fn highest_priority_pending(&self) -> Option<Interrupt> {
+        Interrupt::from_pending(self.pending())
+    }
+
+    // The 3 upper bits of IF are unused and read as 1
+    pub fn read_interrupt_flag(&self) -> u8 {
+        self.interrupt_flag | 0b11100000
+    }
+
+    pub fn write_interrupt_flag(&mut self, value: u8) {
+        self.interrupt_flag = value & 0b00011111
+    }
+}
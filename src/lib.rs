@@ -0,0 +1,14 @@
+pub mod alu;
+pub mod boot_rom;
+pub mod bus;
+pub mod cartridge;
+pub mod cpu;
+pub mod error;
+pub mod instruction;
+pub mod interrupts;
+pub mod mapper;
+pub mod memory;
+pub mod model;
+pub mod save;
+pub mod scheduler;
+pub mod timer;
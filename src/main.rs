@@ -1,25 +1,207 @@
 #![crate_name = "rust_boy"]
 
-mod cpu;
-use cpu::Cpu;
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
 
-mod memory;
-use memory::Memory;
+use rust_boy::boot_rom::BootRom;
+use rust_boy::cartridge::{Cartridge, CartridgeType, CgbSupport};
+use rust_boy::cpu::Cpu;
+use rust_boy::error::EmulatorError;
+use rust_boy::mapper::{self, ClockSource, M_CYCLES_PER_SECOND};
+use rust_boy::memory::Memory;
+use rust_boy::model::Model;
+use rust_boy::save::{self, BatterySave, RtcState};
 
-fn main() {
-    let instructions: Vec<u8> = Vec::from([0b00000001, 0b01000111, 0b10101010, 0b11001110]);
+const USAGE: &str = "Usage:
+    rust-boy run <rom> [--boot-rom <path>] [--rtc cycles|wall-clock]
+        [--save-interval <seconds>|exit]
+    rust-boy info <rom>
+    rust-boy split-save <save> <ram output> <rtc output>
+    rust-boy join-save <ram> <rtc> <save output>";
 
-    let memory: Memory = Memory {
-        ..Memory::default()
+struct RunOptions<'a> {
+    boot_rom: Option<&'a Path>,
+    clock_source: ClockSource,
+    // Emulated seconds between two saves of a dirty cartridge RAM, None to only save on exit
+    save_interval: Option<u64>,
+}
+
+// Parses the options following the ROM of the run command, None on anything unexpected
+fn parse_run_options<'a>(mut options: &[&'a str]) -> Option<RunOptions<'a>> {
+    let mut run_options: RunOptions = RunOptions {
+        boot_rom: None,
+        // Cartridge clocks follow the host time when playing
+        clock_source: ClockSource::WallClock,
+        save_interval: Some(1),
+    };
+    loop {
+        options = match options {
+            [] => return Some(run_options),
+            ["--boot-rom", path, rest @ ..] => {
+                run_options.boot_rom = Some(Path::new(*path));
+                rest
+            }
+            ["--rtc", clock_source, rest @ ..] => {
+                run_options.clock_source = match *clock_source {
+                    "cycles" => ClockSource::Cycles,
+                    "wall-clock" => ClockSource::WallClock,
+                    _ => return None,
+                };
+                rest
+            }
+            ["--save-interval", save_interval, rest @ ..] => {
+                run_options.save_interval = match *save_interval {
+                    "exit" => None,
+                    seconds => Some(seconds.parse().ok()?),
+                };
+                rest
+            }
+            _ => return None,
+        }
+    }
+}
+
+// Prints the cartridge header of the ROM at path
+fn info(path: &Path) -> Result<(), EmulatorError> {
+    let cartridge: Cartridge = Cartridge::from_file(path)?;
+    println!("{}", cartridge.header);
+    let global_checksum_status: &str = if cartridge.is_global_checksum_valid() {
+        "valid"
+    } else {
+        "invalid, ignored by the hardware"
+    };
+    println!("Global checksum is {global_checksum_status}");
+    Ok(())
+}
+
+// Runs the ROM at path, starting from the boot ROM when one is given
+fn run(path: &Path, options: RunOptions) -> Result<(), EmulatorError> {
+    let cartridge: Cartridge = Cartridge::from_file(path)?;
+    let boot_rom: Option<BootRom> = options.boot_rom.map(BootRom::from_file).transpose()?;
+
+    let model: Model = match &boot_rom {
+        Some(boot_rom) if boot_rom.is_cgb() => Model::Cgb,
+        Some(_) => Model::Dmg,
+        None if cartridge.header.cgb_support != CgbSupport::None => Model::Cgb,
+        None => Model::Dmg,
+    };
+    let cartridge_type: &CartridgeType = &cartridge.header.cartridge_type;
+    let has_battery_ram: bool = cartridge_type.has_battery
+        && (mapper::get_ram_size(&cartridge) > 0 || cartridge_type.has_timer);
+    let mut memory: Memory = Memory::new(model);
+    memory.load_cartridge(cartridge, options.clock_source)?;
+
+    let mut battery_save: Option<BatterySave> = has_battery_ram.then(|| {
+        let flush_interval: Option<u64> = options
+            .save_interval
+            .map(|seconds| seconds * M_CYCLES_PER_SECOND);
+        BatterySave::new(path, flush_interval)
+    });
+    if let Some(battery_save) = &battery_save {
+        battery_save.load(&mut memory)?;
+    }
+
+    let mut cpu: Cpu = match boot_rom {
+        Some(boot_rom) => {
+            memory.boot_rom = Some(boot_rom);
+            Cpu {
+                bus: memory,
+                ..Cpu::default()
+            }
+        }
+        None => Cpu::post_boot(model, memory),
     };
 
-    let mut cpu: Cpu = Cpu {
-        memory,
-        instructions,
-        ..Cpu::default()
+    let result: Result<(), EmulatorError> = match &mut battery_save {
+        Some(battery_save) => run_with_battery_save(&mut cpu, battery_save),
+        None => cpu.run(),
     };
+    println!("{cpu:?}");
+    result
+}
+
+// Runs the CPU a frame at a time, saving the cartridge RAM when due and once more on exit
+fn run_with_battery_save(
+    cpu: &mut Cpu,
+    battery_save: &mut BatterySave,
+) -> Result<(), EmulatorError> {
+    const M_CYCLES_PER_FRAME: u64 = 17556;
+
+    let mut has_finished: bool = false;
+    while !has_finished {
+        let frame_end: u64 = cpu.cycle_counter + M_CYCLES_PER_FRAME;
+        cpu.run_until(|cpu, step_result| {
+            has_finished = cpu.has_finished(step_result);
+            has_finished || cpu.cycle_counter >= frame_end
+        });
+        if !has_finished {
+            battery_save.flush_if_due(&mut cpu.bus, cpu.cycle_counter)?;
+        }
+    }
+
+    // A lock up still leaves the RAM worth saving
+    let flush_result: Result<(), EmulatorError> =
+        battery_save.flush(&mut cpu.bus, cpu.cycle_counter);
+    cpu.get_lock_result().and(flush_result)
+}
+
+// Writes the RAM of a save to one file and its clock footer, if any, to an .rtc file
+fn split_save(path: &Path, ram_path: &Path, rtc_path: &Path) -> Result<(), EmulatorError> {
+    let data: Vec<u8> = save::read_file(path)?;
+    let (ram, rtc_state): (&[u8], Option<RtcState>) = save::split_rtc_footer(&data);
+    save::write_atomically(ram_path, ram)?;
+    match rtc_state {
+        Some(rtc_state) => save::write_atomically(rtc_path, &rtc_state.to_footer()),
+        None => {
+            println!("{} has no clock footer", path.display());
+            Ok(())
+        }
+    }
+}
+
+// Appends the clock of an .rtc file to a RAM only save, any footer already there is replaced
+fn join_save(ram_path: &Path, rtc_path: &Path, path: &Path) -> Result<(), EmulatorError> {
+    let data: Vec<u8> = save::read_file(ram_path)?;
+    let (ram, _): (&[u8], Option<RtcState>) = save::split_rtc_footer(&data);
+    let footer: Vec<u8> = save::read_file(rtc_path)?;
+    let rtc_state: RtcState =
+        RtcState::from_footer(&footer).ok_or(EmulatorError::InvalidSaveSize {
+            expected: save::RTC_FOOTER_SIZE,
+            actual: footer.len(),
+        })?;
+
+    let mut joined: Vec<u8> = ram.to_vec();
+    joined.extend(rtc_state.to_footer());
+    save::write_atomically(path, &joined)
+}
+
+fn main() -> ExitCode {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
 
-    cpu.run();
+    let result: Result<(), EmulatorError> = match arguments.as_slice() {
+        ["info", rom] => info(Path::new(rom)),
+        ["split-save", path, ram_path, rtc_path] => {
+            split_save(Path::new(path), Path::new(ram_path), Path::new(rtc_path))
+        }
+        ["join-save", ram_path, rtc_path, path] => {
+            join_save(Path::new(ram_path), Path::new(rtc_path), Path::new(path))
+        }
+        ["run", rom, options @ ..] if let Some(options) = parse_run_options(options) => {
+            run(Path::new(rom), options)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
 
-    println!("{cpu:?}")
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
 }
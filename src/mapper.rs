@@ -0,0 +1,301 @@
+// Mappers are based on https://gbdev.io/pandocs/MBCs.html
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cartridge::{Cartridge, CartridgeType, MapperType, NINTENDO_LOGO};
+use crate::error::EmulatorError;
+use crate::memory::ROM_BANK_SIZE;
+use crate::save::RtcState;
+
+pub mod huc1;
+pub mod huc3;
+pub mod m161;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
+pub mod mmm01;
+pub mod sachen;
+pub mod tama5;
+pub mod wisdom_tree;
+
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+// What makes cartridge clocks advance
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    // Emulated M-cycles, so that runs are reproducible
+    #[default]
+    Cycles,
+    // Host time, so that the clock keeps going while the emulator is closed
+    WallClock,
+}
+
+// Cartridge clocks run from a 32768 Hz crystal, a second lasts 1 MiB M-cycles at normal speed
+pub const M_CYCLES_PER_SECOND: u64 = 0x100000;
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+
+// Counts the seconds elapsed for a cartridge clock from its clock source
+#[derive(Debug)]
+pub struct CartridgeClock {
+    pub clock_source: ClockSource,
+    // M-cycles into the current second when driven by cycles
+    pub sub_second_cycles: u64,
+    pub elapsed_seconds: u64,
+    // Host time the clock was last read when driven by the wall clock
+    pub last_update: SystemTime,
+}
+
+impl CartridgeClock {
+    pub fn new(clock_source: ClockSource) -> CartridgeClock {
+        CartridgeClock {
+            clock_source,
+            sub_second_cycles: 0,
+            elapsed_seconds: 0,
+            last_update: SystemTime::now(),
+        }
+    }
+
+    // Returns whether a second elapsed during these M-cycles
+    pub fn advance(&mut self, m_cycles: u64) -> bool {
+        if self.clock_source != ClockSource::Cycles {
+            return false;
+        }
+        self.sub_second_cycles += m_cycles;
+        let seconds: u64 = self.sub_second_cycles / M_CYCLES_PER_SECOND;
+        self.sub_second_cycles %= M_CYCLES_PER_SECOND;
+        self.elapsed_seconds += seconds;
+        seconds > 0
+    }
+
+    // Seconds elapsed since the last call, the sub-second part is kept for the next one
+    pub fn take_elapsed_seconds(&mut self) -> u64 {
+        if self.clock_source == ClockSource::WallClock {
+            let now: SystemTime = SystemTime::now();
+            match now.duration_since(self.last_update) {
+                Ok(elapsed) => {
+                    self.elapsed_seconds += elapsed.as_secs();
+                    self.last_update += Duration::from_secs(elapsed.as_secs());
+                }
+                // A host clock going backwards is ignored
+                Err(_) => self.last_update = now,
+            }
+        }
+        std::mem::take(&mut self.elapsed_seconds)
+    }
+
+    // Restarts the current second, elapsed seconds must be taken beforehand
+    pub fn reset_sub_second(&mut self) {
+        self.sub_second_cycles = 0;
+        self.last_update = SystemTime::now();
+    }
+
+    // Counts the host time elapsed since a saved Unix timestamp, a clock driven by cycles
+    // resumes where it was saved
+    pub fn catch_up_since(&mut self, timestamp: u64) {
+        if self.clock_source == ClockSource::WallClock {
+            self.elapsed_seconds += get_unix_timestamp().saturating_sub(timestamp);
+        }
+    }
+}
+
+pub fn get_unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// Changes of the cartridge outputs for the host to render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperEvent {
+    RumbleStarted,
+    RumbleStopped,
+}
+
+// Chip of the cartridge handling 0x0000-0x7FFF and 0xA000-0xBFFF
+// The ROM and the RAM are owned by the memory and lent on each access
+pub trait Mapper {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8;
+
+    // Writes to the ROM area set the mapper registers
+    fn write_rom(&mut self, memory_address: u16, value: u8);
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8;
+
+    // Returns whether a byte of ram changed, register and clock writes leave the save untouched
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool;
+
+    // Catches up with the M-cycles elapsed since the last call, for the mappers with a clock
+    // Called before any write to the mapper, reads do not depend on the clock
+    fn advance(&mut self, _m_cycles: u64) {}
+
+    // Called instead of running the boot ROM, for the mappers that react to it
+    fn finish_boot(&mut self) {}
+
+    // Tilt of the console in g for the mappers with an accelerometer
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    // Events since the last call, in the order they happened
+    fn take_events(&mut self) -> Vec<MapperEvent> {
+        Vec::new()
+    }
+
+    // Clock state saved along the cartridge RAM, for the mappers with a clock
+    fn get_rtc_state(&mut self) -> Option<RtcState> {
+        None
+    }
+
+    fn set_rtc_state(&mut self, _rtc_state: &RtcState) {}
+}
+
+// Builds the mapper described by the cartridge header
+pub fn from_cartridge(
+    cartridge: &Cartridge,
+    clock_source: ClockSource,
+) -> Result<Box<dyn Mapper>, EmulatorError> {
+    let cartridge_type: CartridgeType = cartridge.header.cartridge_type;
+    match cartridge_type.mapper {
+        MapperType::None => Ok(Box::new(NoMapper)),
+        MapperType::Mbc1 if mbc1::is_multicart(&cartridge.rom) => {
+            Ok(Box::new(mbc1::Mbc1::new(true)))
+        }
+        MapperType::Mbc1 => Ok(Box::new(mbc1::Mbc1::new(false))),
+        MapperType::Mbc2 => Ok(Box::new(mbc2::Mbc2::default())),
+        MapperType::Mbc3 => {
+            let rtc: Option<mbc3::Rtc> = cartridge_type
+                .has_timer
+                .then(|| mbc3::Rtc::new(clock_source));
+            let is_mbc30: bool = mbc3::is_mbc30(cartridge.rom.len(), cartridge.header.ram_size);
+            Ok(Box::new(mbc3::Mbc3::new(rtc, is_mbc30)))
+        }
+        MapperType::Mbc5 => Ok(Box::new(mbc5::Mbc5::new(cartridge_type.has_rumble))),
+        MapperType::Mbc7 => Ok(Box::new(mbc7::Mbc7::default())),
+        MapperType::HuC1 => Ok(Box::new(huc1::HuC1::default())),
+        MapperType::HuC3 => Ok(Box::new(huc3::HuC3::new(clock_source))),
+        MapperType::Tama5 => Ok(Box::new(tama5::Tama5::new(clock_source))),
+        MapperType::Mmm01 => Ok(Box::new(mmm01::Mmm01::default())),
+        MapperType::WisdomTree => Ok(Box::new(wisdom_tree::WisdomTree::default())),
+        MapperType::M161 => Ok(Box::new(m161::M161::default())),
+        MapperType::SachenMmc1 => Ok(Box::new(sachen::Sachen::new(sachen::SachenVariant::Mmc1))),
+        MapperType::SachenMmc2 => Ok(Box::new(sachen::Sachen::new(sachen::SachenVariant::Mmc2))),
+        _ => Err(EmulatorError::UnsupportedMapper {
+            code: cartridge_type.code,
+        }),
+    }
+}
+
+// Mapper of the cartridges whose header misreports it or sits elsewhere, told apart by size and content
+pub fn detect_misreported(rom: &[u8]) -> Option<MapperType> {
+    if mmm01::is_mmm01(rom) {
+        return Some(MapperType::Mmm01);
+    }
+
+    // Sachen cartridges hide the Nintendo logo from everything but the boot ROM
+    if rom.len() >= 0x0200 && rom[0x0104..0x0134] != NINTENDO_LOGO {
+        for (variant, mapper) in [
+            (sachen::SachenVariant::Mmc1, MapperType::SachenMmc1),
+            (sachen::SachenVariant::Mmc2, MapperType::SachenMmc2),
+        ] {
+            let has_hidden_logo: bool = NINTENDO_LOGO.iter().enumerate().all(|(index, &byte)| {
+                let memory_address: u16 =
+                    sachen::Sachen::get_locked_address(variant, 0x0104 + index as u16);
+                rom[memory_address as usize] == byte
+            });
+            if has_hidden_logo {
+                return Some(mapper);
+            }
+        }
+    }
+
+    match rom[0x0147] {
+        // M161 compilations claim an MBC3 with a timer but no RAM
+        0x10 if rom.len() == 16 * ROM_BANK_SIZE && rom[0x0149] == 0x00 => Some(MapperType::M161),
+        // A cartridge without mapper cannot hold more than 32 KiB, unless Wisdom Tree made it
+        0x00 | 0xC0 if wisdom_tree::is_wisdom_tree(rom) => Some(MapperType::WisdomTree),
+        _ => None,
+    }
+}
+
+// Size of the external RAM, including the RAM built into some mappers that the header reports as 0
+pub fn get_ram_size(cartridge: &Cartridge) -> usize {
+    match cartridge.header.cartridge_type.mapper {
+        MapperType::Mbc2 => mbc2::RAM_SIZE,
+        // The EEPROM stands in for the RAM and is saved the same way
+        MapperType::Mbc7 => mbc7::EEPROM_SIZE,
+        MapperType::Tama5 => tama5::RAM_SIZE,
+        _ => cartridge.header.ram_size,
+    }
+}
+
+// Value the RAM starts with before any save is loaded, an erased EEPROM reads all 1s
+pub fn get_blank_ram_value(cartridge: &Cartridge) -> u8 {
+    match cartridge.header.cartridge_type.mapper {
+        MapperType::Mbc7 => 0xFF,
+        _ => 0x00,
+    }
+}
+
+// Byte at offset of bank, banks past the end wrap around like the unconnected address lines
+pub fn get_banked_value(data: &[u8], bank: usize, bank_size: usize, offset: usize) -> u8 {
+    if data.is_empty() {
+        return 0xFF;
+    }
+    data[(bank * bank_size + offset) % data.len()]
+}
+
+// Returns whether the byte changed
+pub fn set_banked_value(
+    data: &mut [u8],
+    bank: usize,
+    bank_size: usize,
+    offset: usize,
+    value: u8,
+) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+    let length: usize = data.len();
+    let content: &mut u8 = &mut data[(bank * bank_size + offset) % length];
+    let is_changed: bool = *content != value;
+    *content = value;
+    is_changed
+}
+
+// 32 KiB of ROM with up to 8 KiB of RAM directly on the bus
+#[derive(Debug, Default)]
+pub struct NoMapper;
+
+impl Mapper for NoMapper {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        rom.get(memory_address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, _memory_address: u16, _value: u8) {}
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        ram.get((memory_address - 0xA000) as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        match ram.get_mut((memory_address - 0xA000) as usize) {
+            Some(content) if *content != value => {
+                *content = value;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    // Every byte of the ROM holds its bank number
+    pub fn numbered_rom(banks: usize, bank_size: usize) -> Vec<u8> {
+        (0..banks)
+            .flat_map(|bank| vec![bank as u8; bank_size])
+            .collect()
+    }
+}
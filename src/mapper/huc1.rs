@@ -0,0 +1,110 @@
+// HuC1 behaviour is based on https://gbdev.io/pandocs/HuC1.html
+use crate::mapper::{self, Mapper, RAM_BANK_SIZE};
+use crate::memory::ROM_BANK_SIZE;
+
+// Infrared port shared by the Hudson mappers, mapped over the RAM window in IR mode
+#[derive(Debug, Default)]
+pub struct Infrared {
+    pub is_led_on: bool,
+    // Set by the host when light from another device reaches the sensor
+    pub is_light_received: bool,
+}
+
+impl Infrared {
+    pub fn read(&self) -> u8 {
+        0b11000000 | self.is_light_received as u8
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.is_led_on = value & 0b00000001 != 0
+    }
+}
+
+#[derive(Debug)]
+pub struct HuC1 {
+    // There is no RAM enable, 0x0E at 0x0000-0x1FFF maps the IR port instead of the RAM
+    pub is_ir_mode: bool,
+    pub rom_bank: u8, // 6 bits
+    pub ram_bank: u8, // 2 bits
+    pub infrared: Infrared,
+}
+
+impl Default for HuC1 {
+    fn default() -> Self {
+        HuC1 {
+            is_ir_mode: false,
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            infrared: Infrared::default(),
+        }
+    }
+}
+
+impl Mapper for HuC1 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => self.is_ir_mode = value & 0b00001111 == 0x0E,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0b00111111 {
+                    0 => 1,
+                    rom_bank => rom_bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0b00000011,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if self.is_ir_mode {
+            return self.infrared.read();
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::get_banked_value(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if self.is_ir_mode {
+            self.infrared.write(value);
+            return false;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::set_banked_value(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ir_mode_replaces_the_ram_window() {
+        let mut ram: Vec<u8> = vec![0; 4 * RAM_BANK_SIZE];
+        let mut huc1: HuC1 = HuC1::default();
+
+        huc1.write_rom(0x4000, 0x02);
+        huc1.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x42);
+
+        huc1.write_rom(0x0000, 0x0E);
+        assert_eq!(huc1.read_ram(&ram, 0xA000), 0xC0);
+        huc1.infrared.is_light_received = true;
+        assert_eq!(huc1.read_ram(&ram, 0xA000), 0xC1);
+        huc1.write_ram(&mut ram, 0xA000, 0x01);
+        assert!(huc1.infrared.is_led_on);
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x42);
+
+        huc1.write_rom(0x0000, 0x0A);
+        assert_eq!(huc1.read_ram(&ram, 0xA000), 0x42);
+    }
+}
@@ -0,0 +1,264 @@
+// HuC3 behaviour is based on https://gbdev.io/pandocs/HuC3.html
+use crate::mapper::huc1::Infrared;
+use crate::mapper::{self, CartridgeClock, ClockSource, Mapper, RAM_BANK_SIZE};
+use crate::memory::ROM_BANK_SIZE;
+use crate::save::RtcState;
+
+const MINUTES_PER_DAY: u64 = 1440;
+
+// The clock keeps the minute of the day and a 12 bit day counter, exchanged as nibbles through
+// a small memory the game addresses with commands
+#[derive(Debug)]
+pub struct HuC3Rtc {
+    pub clock: CartridgeClock,
+    pub seconds: u8,
+    pub minutes: u16,
+    pub days: u16,
+    pub memory: [u8; 0x100], // 4 bits per address
+    pub address: u8,
+    pub last_command: u8,
+    pub response: u8,
+}
+
+impl HuC3Rtc {
+    pub fn new(clock_source: ClockSource) -> HuC3Rtc {
+        HuC3Rtc {
+            clock: CartridgeClock::new(clock_source),
+            seconds: 0,
+            minutes: 0,
+            days: 0,
+            memory: [0; 0x100],
+            address: 0x00,
+            last_command: 0x00,
+            response: 0x00,
+        }
+    }
+
+    pub fn update(&mut self) {
+        let seconds: u64 = self.seconds as u64 + self.clock.take_elapsed_seconds();
+        let minutes: u64 = self.minutes as u64 + seconds / 60;
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % MINUTES_PER_DAY) as u16;
+        self.days = ((self.days as u64 + minutes / MINUTES_PER_DAY) & 0x0FFF) as u16;
+    }
+
+    // Bits 4-6 hold the command and bits 0-3 its argument
+    pub fn execute(&mut self, value: u8) {
+        let command: u8 = (value >> 4) & 0b00000111;
+        let argument: u8 = value & 0b00001111;
+        self.last_command = command;
+        match command {
+            0x1 => {
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | (argument << 4),
+            0x6 => match argument {
+                // Copy the time to 0x00-0x05, least significant nibble first
+                0x0 => {
+                    self.update();
+                    let time: u32 = ((self.days as u32) << 12) | self.minutes as u32;
+                    for (index, nibble) in self.memory[0x00..0x06].iter_mut().enumerate() {
+                        *nibble = ((time >> (4 * index)) & 0x0F) as u8;
+                    }
+                }
+                // Set the time from 0x00-0x05
+                0x1 => {
+                    self.update();
+                    let time: u32 = self.memory[0x00..0x06]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |time, (index, &nibble)| {
+                            time | ((nibble as u32) << (4 * index))
+                        });
+                    self.seconds = 0;
+                    self.minutes = ((time & 0x0FFF) as u64 % MINUTES_PER_DAY) as u16;
+                    self.days = ((time >> 12) & 0x0FFF) as u16;
+                    self.clock.reset_sub_second();
+                }
+                // Status check, the clock is always fine
+                0x2 => self.response = 0x1,
+                // The tone generator is not emulated
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    pub fn read_response(&self) -> u8 {
+        0b10000000 | (self.last_command << 4) | self.response
+    }
+}
+
+#[derive(Debug)]
+pub struct HuC3 {
+    // Selects what the RAM window maps, written at 0x0000-0x1FFF
+    pub mode: u8,
+    pub rom_bank: u8, // 7 bits
+    pub ram_bank: u8, // 2 bits
+    pub rtc: HuC3Rtc,
+    pub infrared: Infrared,
+}
+
+impl HuC3 {
+    pub fn new(clock_source: ClockSource) -> HuC3 {
+        HuC3 {
+            mode: 0x00,
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            rtc: HuC3Rtc::new(clock_source),
+            infrared: Infrared::default(),
+        }
+    }
+}
+
+impl Mapper for HuC3 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => self.mode = value & 0b00001111,
+            0x2000..=0x3FFF => self.rom_bank = value & 0b01111111,
+            0x4000..=0x5FFF => self.ram_bank = value & 0b00000011,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        match self.mode {
+            // RAM is readable in both read only and read write modes
+            0x00 | 0x0A => {
+                let offset: usize = (memory_address - 0xA000) as usize;
+                mapper::get_banked_value(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset)
+            }
+            0x0C => self.rtc.read_response(),
+            // Commands execute instantly, so the semaphore always reads ready
+            0x0D => 0xFF,
+            0x0E => self.infrared.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        match self.mode {
+            0x0A => {
+                let offset: usize = (memory_address - 0xA000) as usize;
+                return mapper::set_banked_value(
+                    ram,
+                    self.ram_bank as usize,
+                    RAM_BANK_SIZE,
+                    offset,
+                    value,
+                );
+            }
+            0x0B => self.rtc.execute(value),
+            0x0E => self.infrared.write(value),
+            _ => {}
+        }
+        false
+    }
+
+    fn advance(&mut self, m_cycles: u64) {
+        if self.rtc.clock.advance(m_cycles) {
+            self.rtc.update()
+        }
+    }
+
+    // Only whole minutes are saved, the seconds into the current one are moved to the timestamp
+    fn get_rtc_state(&mut self) -> Option<RtcState> {
+        self.rtc.update();
+        Some(RtcState::HuC3 {
+            minutes: self.rtc.minutes,
+            days: self.rtc.days,
+            timestamp: mapper::get_unix_timestamp().saturating_sub(self.rtc.seconds as u64),
+        })
+    }
+
+    fn set_rtc_state(&mut self, rtc_state: &RtcState) {
+        if let RtcState::HuC3 {
+            minutes,
+            days,
+            timestamp,
+        } = *rtc_state
+        {
+            self.rtc.seconds = 0;
+            self.rtc.minutes = (minutes as u64 % MINUTES_PER_DAY) as u16;
+            self.rtc.days = days & 0x0FFF;
+            self.rtc.clock.catch_up_since(timestamp);
+            self.rtc.update()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::M_CYCLES_PER_SECOND;
+
+    fn execute(huc3: &mut HuC3, command: u8, argument: u8) {
+        huc3.write_rom(0x0000, 0x0B);
+        huc3.write_ram(&mut [], 0xA000, (command << 4) | argument);
+    }
+
+    fn read_nibble(huc3: &mut HuC3) -> u8 {
+        execute(huc3, 0x1, 0x0);
+        huc3.write_rom(0x0000, 0x0C);
+        huc3.read_ram(&[], 0xA000) & 0x0F
+    }
+
+    #[test]
+    fn modes_select_ram_rtc_or_infrared() {
+        let mut ram: Vec<u8> = vec![0; 4 * RAM_BANK_SIZE];
+        let mut huc3: HuC3 = HuC3::new(ClockSource::Cycles);
+
+        huc3.write_rom(0x0000, 0x0A);
+        huc3.write_rom(0x4000, 0x01);
+        huc3.write_ram(&mut ram, 0xA000, 0x42);
+        huc3.write_rom(0x0000, 0x00);
+        huc3.write_ram(&mut ram, 0xA000, 0x24);
+        assert_eq!(huc3.read_ram(&ram, 0xA000), 0x42);
+        assert_eq!(ram[RAM_BANK_SIZE], 0x42);
+
+        huc3.write_rom(0x0000, 0x0E);
+        huc3.write_ram(&mut ram, 0xA000, 0x01);
+        assert!(huc3.infrared.is_led_on);
+        assert_eq!(huc3.read_ram(&ram, 0xA000), 0xC0);
+    }
+
+    #[test]
+    fn time_is_set_and_read_through_commands() {
+        let mut huc3: HuC3 = HuC3::new(ClockSource::Cycles);
+
+        // Day 0x123 at minute 1439
+        execute(&mut huc3, 0x4, 0x0);
+        execute(&mut huc3, 0x5, 0x0);
+        for nibble in [0xF, 0x9, 0x5, 0x3, 0x2, 0x1] {
+            execute(&mut huc3, 0x3, nibble);
+        }
+        execute(&mut huc3, 0x6, 0x1);
+
+        huc3.advance(60 * M_CYCLES_PER_SECOND);
+        execute(&mut huc3, 0x6, 0x0);
+        execute(&mut huc3, 0x4, 0x0);
+        execute(&mut huc3, 0x5, 0x0);
+        let nibbles: Vec<u8> = (0..6).map(|_| read_nibble(&mut huc3)).collect();
+        assert_eq!(nibbles, [0x0, 0x0, 0x0, 0x4, 0x2, 0x1]);
+
+        execute(&mut huc3, 0x6, 0x2);
+        huc3.write_rom(0x0000, 0x0C);
+        assert_eq!(huc3.read_ram(&[], 0xA000), 0b11100001);
+    }
+}
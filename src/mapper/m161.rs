@@ -0,0 +1,55 @@
+// M161 behaviour is based on https://gbdev.io/pandocs/M161.html
+use crate::mapper::{self, Mapper};
+
+const BANK_SIZE: usize = 0x8000;
+
+// Used by the Mani 4 in 1 compilations, the menu picks a 32 KiB game once until the next reset
+#[derive(Debug, Default)]
+pub struct M161 {
+    pub rom_bank: u8, // 3 bits
+    pub is_locked: bool,
+}
+
+impl Mapper for M161 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        mapper::get_banked_value(
+            rom,
+            self.rom_bank as usize,
+            BANK_SIZE,
+            memory_address as usize,
+        )
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        if (0x4000..=0x5FFF).contains(&memory_address) && !self.is_locked {
+            self.rom_bank = value & 0b00000111;
+            self.is_locked = true
+        }
+    }
+
+    fn read_ram(&self, _ram: &[u8], _memory_address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _ram: &mut [u8], _memory_address: u16, _value: u8) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn first_bank_write_locks_the_mapper() {
+        let rom: Vec<u8> = numbered_rom(8, BANK_SIZE);
+        let mut m161: M161 = M161::default();
+        assert_eq!(m161.read_rom(&rom, 0x4000), 0x00);
+
+        m161.write_rom(0x4000, 0x0B);
+        assert_eq!(m161.read_rom(&rom, 0x0000), 0x03);
+        m161.write_rom(0x4000, 0x05);
+        assert_eq!(m161.read_rom(&rom, 0x7FFF), 0x03);
+    }
+}
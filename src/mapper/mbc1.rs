@@ -0,0 +1,187 @@
+// MBC1 and MBC1M behaviour is based on https://gbdev.io/pandocs/MBC1.html
+use crate::cartridge::NINTENDO_LOGO;
+use crate::mapper::{self, Mapper, RAM_BANK_SIZE};
+use crate::memory::ROM_BANK_SIZE;
+
+#[derive(Debug)]
+pub struct Mbc1 {
+    pub is_ram_enabled: bool,
+    pub rom_bank: u8,           // 5 bits, written at 0x2000-0x3FFF
+    pub secondary_bank: u8,     // 2 bits, written at 0x4000-0x5FFF
+    pub is_advanced_mode: bool, // Banking mode 1, written at 0x6000-0x7FFF
+    // MBC1M multicarts leave bit 4 of the ROM bank unconnected
+    pub is_multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(is_multicart: bool) -> Mbc1 {
+        Mbc1 {
+            is_ram_enabled: false,
+            rom_bank: 0x01,
+            secondary_bank: 0x00,
+            is_advanced_mode: false,
+            is_multicart,
+        }
+    }
+
+    // Number of bits the secondary register is shifted by in ROM bank numbers
+    fn get_secondary_bank_shift(&self) -> u8 {
+        if self.is_multicart { 4 } else { 5 }
+    }
+
+    fn get_low_rom_bank(&self) -> usize {
+        if self.is_advanced_mode {
+            (self.secondary_bank << self.get_secondary_bank_shift()) as usize
+        } else {
+            0
+        }
+    }
+
+    fn get_high_rom_bank(&self) -> usize {
+        let rom_bank: u8 = if self.is_multicart {
+            self.rom_bank & 0b00001111
+        } else {
+            self.rom_bank
+        };
+        ((self.secondary_bank << self.get_secondary_bank_shift()) | rom_bank) as usize
+    }
+
+    fn get_ram_bank(&self) -> usize {
+        if self.is_advanced_mode {
+            self.secondary_bank as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            self.get_low_rom_bank()
+        } else {
+            self.get_high_rom_bank()
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => self.is_ram_enabled = value & 0b00001111 == 0x0A,
+            // Bank 0 is turned into bank 1 before the upper bits get added, so 0x20 maps to 0x21
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0b00011111 {
+                    0 => 1,
+                    rom_bank => rom_bank,
+                }
+            }
+            0x4000..=0x5FFF => self.secondary_bank = value & 0b00000011,
+            _ => self.is_advanced_mode = value & 0b00000001 != 0,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if !self.is_ram_enabled {
+            return 0xFF;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::get_banked_value(ram, self.get_ram_bank(), RAM_BANK_SIZE, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.is_ram_enabled {
+            return false;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::set_banked_value(ram, self.get_ram_bank(), RAM_BANK_SIZE, offset, value)
+    }
+}
+
+// MBC1M multicarts are 1 MiB and hold a game with its own header every 256 KiB
+// Games from these compilations boot from bank 0x10 with a copy of the Nintendo logo
+pub fn is_multicart(rom: &[u8]) -> bool {
+    const SECOND_GAME_LOGO_START: usize = 0x10 * ROM_BANK_SIZE + 0x0104;
+
+    rom.len() == 64 * ROM_BANK_SIZE
+        && rom[SECOND_GAME_LOGO_START..SECOND_GAME_LOGO_START + NINTENDO_LOGO.len()]
+            == NINTENDO_LOGO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn bank_0_selects_bank_1_before_the_upper_bits() {
+        let rom: Vec<u8> = numbered_rom(128, ROM_BANK_SIZE);
+        let mut mbc1: Mbc1 = Mbc1::new(false);
+        assert_eq!(mbc1.read_rom(&rom, 0x4000), 0x01);
+
+        mbc1.write_rom(0x2000, 0x00);
+        assert_eq!(mbc1.read_rom(&rom, 0x4000), 0x01);
+        mbc1.write_rom(0x2000, 0xE5);
+        assert_eq!(mbc1.read_rom(&rom, 0x7FFF), 0x05);
+
+        mbc1.write_rom(0x4000, 0x01);
+        mbc1.write_rom(0x2000, 0x00);
+        assert_eq!(mbc1.read_rom(&rom, 0x4000), 0x21);
+        assert_eq!(mbc1.read_rom(&rom, 0x0000), 0x00);
+
+        // Mode 1 maps the secondary bank to 0x0000-0x3FFF as well
+        mbc1.write_rom(0x6000, 0x01);
+        assert_eq!(mbc1.read_rom(&rom, 0x0000), 0x20);
+
+        // Banks past the end of the ROM wrap around
+        let small_rom: Vec<u8> = numbered_rom(8, ROM_BANK_SIZE);
+        mbc1.write_rom(0x2000, 0x0A);
+        assert_eq!(mbc1.read_rom(&small_rom, 0x4000), 0x02);
+    }
+
+    #[test]
+    fn ram_is_gated_and_banked_in_mode_1() {
+        let mut ram: Vec<u8> = vec![0; 4 * RAM_BANK_SIZE];
+        let mut mbc1: Mbc1 = Mbc1::new(false);
+
+        mbc1.write_ram(&mut ram, 0xA000, 0x11);
+        assert_eq!(mbc1.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc1.write_rom(0x0000, 0x0A);
+        mbc1.write_ram(&mut ram, 0xA000, 0x11);
+        mbc1.write_rom(0x4000, 0x02);
+        // Mode 0 always uses RAM bank 0
+        assert_eq!(mbc1.read_ram(&ram, 0xA000), 0x11);
+
+        mbc1.write_rom(0x6000, 0x01);
+        mbc1.write_ram(&mut ram, 0xA000, 0x22);
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x22);
+        assert_eq!(ram[0], 0x11);
+
+        mbc1.write_rom(0x0000, 0x00);
+        assert_eq!(mbc1.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn multicarts_use_4_bits_of_the_rom_bank() {
+        // Matching padding is not a logo
+        let mut rom: Vec<u8> = numbered_rom(64, ROM_BANK_SIZE);
+        rom[0x0104..0x0134].fill(0xFF);
+        rom[0x40104..0x40134].fill(0xFF);
+        assert!(!is_multicart(&rom));
+        rom[0x40104..0x40134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(is_multicart(&rom));
+
+        let mut mbc1: Mbc1 = Mbc1::new(true);
+        mbc1.write_rom(0x2000, 0x12);
+        mbc1.write_rom(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(&rom, 0x4000), 0x12);
+
+        mbc1.write_rom(0x6000, 0x01);
+        assert_eq!(mbc1.read_rom(&rom, 0x0000), 0x10);
+
+        // The zero check still looks at the 5 bits
+        mbc1.write_rom(0x2000, 0x10);
+        assert_eq!(mbc1.read_rom(&rom, 0x4000), 0x10);
+    }
+}
@@ -0,0 +1,106 @@
+// MBC2 behaviour is based on https://gbdev.io/pandocs/MBC2.html
+use crate::mapper::{self, Mapper};
+use crate::memory::ROM_BANK_SIZE;
+
+// 512 half-bytes, only the lower nibble of each byte is used
+pub const RAM_SIZE: usize = 0x0200;
+
+#[derive(Debug)]
+pub struct Mbc2 {
+    pub is_ram_enabled: bool,
+    pub rom_bank: u8, // 4 bits
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Mbc2 {
+            is_ram_enabled: false,
+            rom_bank: 0x01,
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    // Only 0x0000-0x3FFF is decoded, address bit 8 selects the register
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x3FFF if memory_address & 0x0100 == 0 => {
+                self.is_ram_enabled = value & 0b00001111 == 0x0A
+            }
+            0x0000..=0x3FFF => {
+                self.rom_bank = match value & 0b00001111 {
+                    0 => 1,
+                    rom_bank => rom_bank,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The 512 half-bytes echo across 0xA000-0xBFFF and the upper nibble is open bus
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if !self.is_ram_enabled {
+            return 0xFF;
+        }
+        0b11110000 | mapper::get_banked_value(ram, 0, RAM_SIZE, (memory_address & 0x01FF) as usize)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.is_ram_enabled {
+            return false;
+        }
+        let offset: usize = (memory_address & 0x01FF) as usize;
+        mapper::set_banked_value(ram, 0, RAM_SIZE, offset, value & 0b00001111)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn address_bit_8_selects_the_register() {
+        let rom: Vec<u8> = numbered_rom(16, ROM_BANK_SIZE);
+        let mut mbc2: Mbc2 = Mbc2::default();
+
+        mbc2.write_rom(0x2000, 0x03);
+        assert_eq!(mbc2.read_rom(&rom, 0x4000), 0x01);
+        assert!(!mbc2.is_ram_enabled);
+        mbc2.write_rom(0x2100, 0xF3);
+        assert_eq!(mbc2.read_rom(&rom, 0x4000), 0x03);
+        mbc2.write_rom(0x0100, 0x00);
+        assert_eq!(mbc2.read_rom(&rom, 0x7FFF), 0x01);
+        assert_eq!(mbc2.read_rom(&rom, 0x0000), 0x00);
+
+        mbc2.write_rom(0x3EFF, 0x0A);
+        assert!(mbc2.is_ram_enabled);
+        // 0x4000-0x7FFF is not decoded
+        mbc2.write_rom(0x4100, 0x05);
+        assert_eq!(mbc2.read_rom(&rom, 0x4000), 0x01);
+    }
+
+    #[test]
+    fn ram_holds_half_bytes_echoed_across_the_area() {
+        let mut ram: Vec<u8> = vec![0; RAM_SIZE];
+        let mut mbc2: Mbc2 = Mbc2::default();
+        assert_eq!(mbc2.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc2.write_rom(0x0000, 0x0A);
+        mbc2.write_ram(&mut ram, 0xA001, 0xAB);
+        assert_eq!(ram[1], 0x0B);
+        assert_eq!(mbc2.read_ram(&ram, 0xA001), 0xFB);
+        assert_eq!(mbc2.read_ram(&ram, 0xA201), 0xFB);
+        assert_eq!(mbc2.read_ram(&ram, 0xBE01), 0xFB);
+    }
+}
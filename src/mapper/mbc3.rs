@@ -0,0 +1,433 @@
+// MBC3 and its real-time clock are based on https://gbdev.io/pandocs/MBC3.html
+use crate::mapper::{self, CartridgeClock, ClockSource, Mapper, RAM_BANK_SIZE, SECONDS_PER_DAY};
+use crate::memory::ROM_BANK_SIZE;
+use crate::save::RtcState;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,   // 6 bits
+    pub minutes: u8,   // 6 bits
+    pub hours: u8,     // 5 bits
+    pub days_low: u8,  // Lower 8 bits of the day counter
+    pub days_high: u8, // Bit 0 is the day counter msb, bit 6 halts the clock and bit 7 is the day carry
+}
+
+impl RtcRegisters {
+    pub const DAY_MSB: u8 = 0b00000001;
+    pub const HALT: u8 = 0b01000000;
+    pub const DAY_CARRY: u8 = 0b10000000;
+
+    pub fn is_halted(&self) -> bool {
+        self.days_high & RtcRegisters::HALT != 0
+    }
+
+    pub fn get_days(&self) -> u16 {
+        (((self.days_high & RtcRegisters::DAY_MSB) as u16) << 8) | self.days_low as u16
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.days_low = days as u8;
+        self.days_high =
+            (self.days_high & !RtcRegisters::DAY_MSB) | ((days >> 8) as u8 & RtcRegisters::DAY_MSB);
+    }
+
+    fn is_in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    // Counters only wrap at their expected value, out of range values count up to their bit width first
+    fn increment_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0b00111111;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0b00111111;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0b00011111;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.increment_days(1);
+    }
+
+    fn increment_days(&mut self, days: u64) {
+        let days: u64 = self.get_days() as u64 + days;
+        if days > 0x01FF {
+            self.days_high |= RtcRegisters::DAY_CARRY;
+        }
+        self.set_days((days & 0x01FF) as u16);
+    }
+
+    // Seconds, minutes, hours, days low and days high in register order
+    pub fn to_array(self) -> [u32; 5] {
+        [
+            self.seconds as u32,
+            self.minutes as u32,
+            self.hours as u32,
+            self.days_low as u32,
+            self.days_high as u32,
+        ]
+    }
+
+    pub fn from_array(registers: [u32; 5]) -> RtcRegisters {
+        RtcRegisters {
+            seconds: registers[0] as u8 & 0b00111111,
+            minutes: registers[1] as u8 & 0b00111111,
+            hours: registers[2] as u8 & 0b00011111,
+            days_low: registers[3] as u8,
+            days_high: registers[4] as u8 & 0b11000001,
+        }
+    }
+
+    pub fn advance(&mut self, mut seconds: u64) {
+        while seconds > 0 && !self.is_in_range() {
+            self.increment_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let time_of_day: u64 =
+            self.seconds as u64 + 60 * self.minutes as u64 + 3600 * self.hours as u64 + seconds;
+        let time: u64 = time_of_day % SECONDS_PER_DAY;
+        self.seconds = (time % 60) as u8;
+        self.minutes = (time / 60 % 60) as u8;
+        self.hours = (time / 3600) as u8;
+        self.increment_days(time_of_day / SECONDS_PER_DAY);
+    }
+}
+
+#[derive(Debug)]
+pub struct Rtc {
+    pub clock: CartridgeClock,
+    pub registers: RtcRegisters,
+    pub latched_registers: RtcRegisters,
+}
+
+impl Rtc {
+    pub fn new(clock_source: ClockSource) -> Rtc {
+        Rtc {
+            clock: CartridgeClock::new(clock_source),
+            registers: RtcRegisters::default(),
+            latched_registers: RtcRegisters::default(),
+        }
+    }
+
+    pub fn advance(&mut self, m_cycles: u64) {
+        if !self.registers.is_halted() && self.clock.advance(m_cycles) {
+            self.update()
+        }
+    }
+
+    // Brings the registers up to date, time elapsed while halted is dropped
+    pub fn update(&mut self) {
+        let seconds: u64 = self.clock.take_elapsed_seconds();
+        if !self.registers.is_halted() {
+            self.registers.advance(seconds);
+        }
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched_registers = self.registers;
+    }
+
+    // register is the value written to 0x4000-0x5FFF, between 0x08 and 0x0C
+    pub fn read_register(&self, register: u8) -> u8 {
+        let registers: &RtcRegisters = &self.latched_registers;
+        match register {
+            0x08 => registers.seconds,
+            0x09 => registers.minutes,
+            0x0A => registers.hours,
+            0x0B => registers.days_low,
+            _ => registers.days_high,
+        }
+    }
+
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        self.update();
+        match register {
+            // Writing the seconds resets the sub-second divider
+            0x08 => {
+                self.registers.seconds = value & 0b00111111;
+                self.clock.reset_sub_second()
+            }
+            0x09 => self.registers.minutes = value & 0b00111111,
+            0x0A => self.registers.hours = value & 0b00011111,
+            0x0B => self.registers.days_low = value,
+            _ => self.registers.days_high = value & 0b11000001,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc3 {
+    pub is_ram_and_rtc_enabled: bool,
+    pub rom_bank: u8,
+    // 0x00-0x07 select a RAM bank and 0x08-0x0C an RTC register
+    pub ram_bank_or_rtc_register: u8,
+    pub last_latch_write: u8,
+    pub rtc: Option<Rtc>,
+    // MBC30 has an 8 bit ROM bank and 8 RAM banks
+    pub is_mbc30: bool,
+}
+
+impl Mbc3 {
+    pub fn new(rtc: Option<Rtc>, is_mbc30: bool) -> Mbc3 {
+        Mbc3 {
+            is_ram_and_rtc_enabled: false,
+            rom_bank: 0x01,
+            ram_bank_or_rtc_register: 0x00,
+            last_latch_write: 0xFF,
+            rtc,
+            is_mbc30,
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => self.is_ram_and_rtc_enabled = value & 0b00001111 == 0x0A,
+            0x2000..=0x3FFF => {
+                let mask: u8 = if self.is_mbc30 {
+                    0b11111111
+                } else {
+                    0b01111111
+                };
+                self.rom_bank = match value & mask {
+                    0 => 1,
+                    rom_bank => rom_bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank_or_rtc_register = value & 0b00001111,
+            // Writing 0x00 then 0x01 latches the clock
+            _ => {
+                if self.last_latch_write == 0x00
+                    && value == 0x01
+                    && let Some(rtc) = self.rtc.as_mut()
+                {
+                    rtc.latch()
+                }
+                self.last_latch_write = value
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if !self.is_ram_and_rtc_enabled {
+            return 0xFF;
+        }
+        let ram_banks: u8 = if self.is_mbc30 { 8 } else { 4 };
+        match (self.ram_bank_or_rtc_register, &self.rtc) {
+            (bank, _) if bank < ram_banks => {
+                let offset: usize = (memory_address - 0xA000) as usize;
+                mapper::get_banked_value(ram, bank as usize, RAM_BANK_SIZE, offset)
+            }
+            (register @ 0x08..=0x0C, Some(rtc)) => rtc.read_register(register),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.is_ram_and_rtc_enabled {
+            return false;
+        }
+        let ram_banks: u8 = if self.is_mbc30 { 8 } else { 4 };
+        match (self.ram_bank_or_rtc_register, self.rtc.as_mut()) {
+            (bank, _) if bank < ram_banks => {
+                let offset: usize = (memory_address - 0xA000) as usize;
+                return mapper::set_banked_value(ram, bank as usize, RAM_BANK_SIZE, offset, value);
+            }
+            (register @ 0x08..=0x0C, Some(rtc)) => rtc.write_register(register, value),
+            _ => {}
+        }
+        false
+    }
+
+    fn advance(&mut self, m_cycles: u64) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.advance(m_cycles)
+        }
+    }
+
+    fn get_rtc_state(&mut self) -> Option<RtcState> {
+        let rtc: &mut Rtc = self.rtc.as_mut()?;
+        rtc.update();
+        Some(RtcState::Mbc3 {
+            registers: rtc.registers.to_array(),
+            latched_registers: rtc.latched_registers.to_array(),
+            timestamp: mapper::get_unix_timestamp(),
+        })
+    }
+
+    // Time elapsed since the save is counted unless the saved clock was halted
+    fn set_rtc_state(&mut self, rtc_state: &RtcState) {
+        if let RtcState::Mbc3 {
+            registers,
+            latched_registers,
+            timestamp,
+        } = *rtc_state
+            && let Some(rtc) = self.rtc.as_mut()
+        {
+            rtc.registers = RtcRegisters::from_array(registers);
+            rtc.latched_registers = RtcRegisters::from_array(latched_registers);
+            rtc.clock.catch_up_since(timestamp);
+            rtc.update()
+        }
+    }
+}
+
+// MBC30 is only told apart by a ROM above 2 MiB or a RAM above 32 KiB
+pub fn is_mbc30(rom_size: usize, ram_size: usize) -> bool {
+    rom_size > 128 * ROM_BANK_SIZE || ram_size > 4 * RAM_BANK_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::M_CYCLES_PER_SECOND;
+    use crate::mapper::tests::numbered_rom;
+
+    fn enabled_mbc3() -> Mbc3 {
+        let mut mbc3: Mbc3 = Mbc3::new(Some(Rtc::new(ClockSource::Cycles)), false);
+        mbc3.write_rom(0x0000, 0x0A);
+        mbc3
+    }
+
+    fn latch(mbc3: &mut Mbc3) {
+        mbc3.write_rom(0x6000, 0x00);
+        mbc3.write_rom(0x6000, 0x01);
+    }
+
+    fn read_rtc_register(mbc3: &mut Mbc3, register: u8) -> u8 {
+        mbc3.write_rom(0x4000, register);
+        mbc3.read_ram(&[], 0xA000)
+    }
+
+    fn write_rtc_register(mbc3: &mut Mbc3, register: u8, value: u8) {
+        mbc3.write_rom(0x4000, register);
+        mbc3.write_ram(&mut [], 0xA000, value);
+    }
+
+    #[test]
+    fn banks_rom_and_ram() {
+        let rom: Vec<u8> = numbered_rom(256, ROM_BANK_SIZE);
+        let mut ram: Vec<u8> = vec![0; 8 * RAM_BANK_SIZE];
+
+        let mut mbc3: Mbc3 = enabled_mbc3();
+        mbc3.write_rom(0x2000, 0x00);
+        assert_eq!(mbc3.read_rom(&rom, 0x4000), 0x01);
+        mbc3.write_rom(0x2000, 0xC5);
+        assert_eq!(mbc3.read_rom(&rom, 0x4000), 0x45);
+        mbc3.write_rom(0x4000, 0x03);
+        mbc3.write_ram(&mut ram, 0xA010, 0x33);
+        assert_eq!(ram[3 * RAM_BANK_SIZE + 0x10], 0x33);
+        mbc3.write_rom(0x4000, 0x05);
+        assert_eq!(mbc3.read_ram(&ram, 0xA010), 0xFF);
+
+        let mut mbc30: Mbc3 = Mbc3::new(None, true);
+        mbc30.write_rom(0x0000, 0x0A);
+        mbc30.write_rom(0x2000, 0xC5);
+        assert_eq!(mbc30.read_rom(&rom, 0x4000), 0xC5);
+        mbc30.write_rom(0x4000, 0x05);
+        mbc30.write_ram(&mut ram, 0xA010, 0x55);
+        assert_eq!(mbc30.read_ram(&ram, 0xA010), 0x55);
+        // Without a timer the RTC registers are open bus
+        assert_eq!(read_rtc_register(&mut mbc30, 0x08), 0xFF);
+    }
+
+    #[test]
+    fn clock_driven_by_cycles_is_read_through_the_latch() {
+        let mut mbc3: Mbc3 = enabled_mbc3();
+        write_rtc_register(&mut mbc3, 0x08, 59);
+        write_rtc_register(&mut mbc3, 0x09, 59);
+        write_rtc_register(&mut mbc3, 0x0A, 23);
+        write_rtc_register(&mut mbc3, 0x0B, 0xFF);
+        write_rtc_register(&mut mbc3, 0x0C, 0x01);
+
+        mbc3.advance(M_CYCLES_PER_SECOND);
+        // Nothing changes until the next latch
+        assert_eq!(read_rtc_register(&mut mbc3, 0x08), 0);
+        latch(&mut mbc3);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x08), 0);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x09), 0);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x0A), 0);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x0B), 0);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x0C), RtcRegisters::DAY_CARRY);
+
+        // Halting stops the clock
+        write_rtc_register(&mut mbc3, 0x0C, RtcRegisters::HALT);
+        mbc3.advance(2 * M_CYCLES_PER_SECOND);
+        latch(&mut mbc3);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x08), 0);
+        assert_eq!(read_rtc_register(&mut mbc3, 0x0C), RtcRegisters::HALT);
+    }
+
+    #[test]
+    fn out_of_range_values_count_up_to_their_bit_width() {
+        let mut registers: RtcRegisters = RtcRegisters {
+            seconds: 62,
+            minutes: 59,
+            ..RtcRegisters::default()
+        };
+        registers.advance(2);
+        assert_eq!((registers.seconds, registers.minutes), (0, 59));
+        registers.advance(60);
+        assert_eq!(
+            (registers.seconds, registers.minutes, registers.hours),
+            (0, 0, 1)
+        );
+
+        registers.advance(SECONDS_PER_DAY * 600 + 61);
+        assert_eq!(registers.get_days(), 600 - 512);
+        assert_eq!(
+            (registers.seconds, registers.minutes, registers.hours),
+            (1, 1, 1)
+        );
+        assert_ne!(registers.days_high & RtcRegisters::DAY_CARRY, 0);
+    }
+
+    fn get_saved_registers(mbc3: &mut Mbc3) -> [u32; 5] {
+        match mbc3.get_rtc_state() {
+            Some(RtcState::Mbc3 { registers, .. }) => registers,
+            rtc_state => panic!("Unexpected clock state {rtc_state:?}"),
+        }
+    }
+
+    #[test]
+    fn wall_clock_catches_up_with_the_time_since_the_save() {
+        let mut mbc3: Mbc3 = Mbc3::new(Some(Rtc::new(ClockSource::WallClock)), false);
+        mbc3.set_rtc_state(&RtcState::Mbc3 {
+            registers: [0, 30, 5, 0x00, 0x00],
+            latched_registers: [0; 5],
+            timestamp: mapper::get_unix_timestamp() - 2 * 3600,
+        });
+        assert_eq!(&get_saved_registers(&mut mbc3)[1..], &[30, 7, 0x00, 0x00]);
+
+        // A halted clock ignores the time elapsed since the save
+        let halted_registers: [u32; 5] = [0, 30, 5, 0x00, RtcRegisters::HALT as u32];
+        mbc3.set_rtc_state(&RtcState::Mbc3 {
+            registers: halted_registers,
+            latched_registers: [0; 5],
+            timestamp: mapper::get_unix_timestamp() - 2 * 3600,
+        });
+        assert_eq!(get_saved_registers(&mut mbc3), halted_registers);
+    }
+}
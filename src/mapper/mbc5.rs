@@ -0,0 +1,143 @@
+// MBC5 behaviour is based on https://gbdev.io/pandocs/MBC5.html
+use crate::mapper::{self, Mapper, MapperEvent, RAM_BANK_SIZE};
+use crate::memory::ROM_BANK_SIZE;
+
+#[derive(Debug)]
+pub struct Mbc5 {
+    pub is_ram_enabled: bool,
+    pub rom_bank: u16, // 9 bits, bank 0 can be selected
+    pub ram_bank: u8,  // 4 bits, 3 bits on rumble cartridges
+    // Rumble cartridges wire bit 3 of the RAM bank register to the motor
+    pub has_rumble: bool,
+    pub is_rumbling: bool,
+    pub events: Vec<MapperEvent>,
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            is_ram_enabled: false,
+            rom_bank: 0x0001,
+            ram_bank: 0x00,
+            has_rumble,
+            is_rumbling: false,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            // Unlike older mappers, the whole byte is compared
+            0x0000..=0x1FFF => self.is_ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x0100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x00FF) | (((value & 0b00000001) as u16) << 8)
+            }
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.ram_bank = value & 0b00000111;
+                let is_rumbling: bool = value & 0b00001000 != 0;
+                if is_rumbling != self.is_rumbling {
+                    self.is_rumbling = is_rumbling;
+                    self.events.push(if is_rumbling {
+                        MapperEvent::RumbleStarted
+                    } else {
+                        MapperEvent::RumbleStopped
+                    });
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0b00001111,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if !self.is_ram_enabled {
+            return 0xFF;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::get_banked_value(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.is_ram_enabled {
+            return false;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::set_banked_value(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset, value)
+    }
+
+    fn take_events(&mut self) -> Vec<MapperEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn selects_9_bit_rom_banks_including_bank_0() {
+        let mut rom: Vec<u8> = numbered_rom(512, ROM_BANK_SIZE);
+        // The second byte of each bank holds the 9th bit of its number
+        for bank in 256..512 {
+            rom[bank * ROM_BANK_SIZE + 1] = 0x01;
+        }
+        let mut mbc5: Mbc5 = Mbc5::new(false);
+        assert_eq!(mbc5.read_rom(&rom, 0x4000), 0x01);
+
+        mbc5.write_rom(0x2000, 0x00);
+        assert_eq!(mbc5.read_rom(&rom, 0x4000), 0x00);
+        mbc5.write_rom(0x3000, 0xFF);
+        mbc5.write_rom(0x2000, 0x23);
+        assert_eq!(mbc5.read_rom(&rom, 0x4000), 0x23);
+        assert_eq!(mbc5.read_rom(&rom, 0x4001), 0x01);
+        assert_eq!(mbc5.read_rom(&rom, 0x0001), 0x00);
+    }
+
+    #[test]
+    fn ram_needs_exactly_0x0a_and_has_16_banks() {
+        let mut ram: Vec<u8> = vec![0; 16 * RAM_BANK_SIZE];
+        let mut mbc5: Mbc5 = Mbc5::new(false);
+
+        mbc5.write_rom(0x0000, 0x1A);
+        mbc5.write_ram(&mut ram, 0xA000, 0x11);
+        assert_eq!(mbc5.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc5.write_rom(0x0000, 0x0A);
+        mbc5.write_rom(0x4000, 0x0F);
+        mbc5.write_ram(&mut ram, 0xA000, 0x11);
+        assert_eq!(ram[15 * RAM_BANK_SIZE], 0x11);
+        assert!(mbc5.take_events().is_empty());
+    }
+
+    #[test]
+    fn rumble_transitions_are_reported_once() {
+        let mut ram: Vec<u8> = vec![0; 8 * RAM_BANK_SIZE];
+        let mut mbc5: Mbc5 = Mbc5::new(true);
+        mbc5.write_rom(0x0000, 0x0A);
+
+        mbc5.write_rom(0x4000, 0x0B);
+        mbc5.write_rom(0x4000, 0x0B);
+        mbc5.write_ram(&mut ram, 0xA000, 0x33);
+        assert_eq!(ram[3 * RAM_BANK_SIZE], 0x33);
+        mbc5.write_rom(0x4000, 0x03);
+        assert_eq!(
+            mbc5.take_events(),
+            vec![MapperEvent::RumbleStarted, MapperEvent::RumbleStopped]
+        );
+        assert!(mbc5.take_events().is_empty());
+    }
+}
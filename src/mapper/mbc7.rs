@@ -0,0 +1,411 @@
+// MBC7 behaviour is based on https://gbdev.io/pandocs/MBC7.html
+// EEPROM commands are based on the 93LC56 datasheet in 16 bit organization
+use crate::mapper::{self, Mapper};
+use crate::memory::ROM_BANK_SIZE;
+
+// 128 words of 16 bits, stored as little endian bytes in the external RAM
+pub const EEPROM_SIZE: usize = 0x0100;
+
+// Accelerometer reading at rest and change for 1 g of tilt
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+const ACCELEROMETER_ONE_G: f32 = 0x70 as f32;
+const ACCELEROMETER_ERASED: u16 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EepromState {
+    // Waiting for a start bit
+    Idle,
+    // Shifting in the 2 op code bits and the 8 address bits
+    Command {
+        bits: u16,
+        count: u8,
+    },
+    // Shifting out a word, most significant bit first
+    Reading {
+        data: u16,
+        count: u8,
+    },
+    // Shifting in a word for address, or for every word when None
+    Writing {
+        address: Option<u8>,
+        data: u16,
+        count: u8,
+    },
+    // Waiting for chip select to go low
+    Done,
+}
+
+#[derive(Debug)]
+pub struct Eeprom {
+    pub state: EepromState,
+    pub is_write_enabled: bool,
+    pub chip_select: bool,
+    pub clock: bool,
+    pub data_in: bool,
+    pub data_out: bool,
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Eeprom {
+            state: EepromState::Idle,
+            is_write_enabled: false,
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+        }
+    }
+}
+
+impl Eeprom {
+    fn get_word(storage: &[u8], address: u8) -> u16 {
+        let index: usize = (2 * address as usize) % EEPROM_SIZE;
+        match storage.get(index..index + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+            None => 0xFFFF,
+        }
+    }
+
+    // Returns whether the word changed
+    fn set_word(&self, storage: &mut [u8], address: u8, word: u16) -> bool {
+        let index: usize = (2 * address as usize) % EEPROM_SIZE;
+        match storage.get_mut(index..index + 2) {
+            Some(bytes) if self.is_write_enabled && *bytes != word.to_le_bytes() => {
+                bytes.copy_from_slice(&word.to_le_bytes());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn set_all_words(&self, storage: &mut [u8], word: u16) -> bool {
+        let mut is_changed: bool = false;
+        for address in 0..=0x7F {
+            is_changed |= self.set_word(storage, address, word);
+        }
+        is_changed
+    }
+
+    pub fn read_pins(&self) -> u8 {
+        ((self.chip_select as u8) << 7)
+            | ((self.clock as u8) << 6)
+            | ((self.data_in as u8) << 1)
+            | self.data_out as u8
+    }
+
+    // Bit 7 is chip select, bit 6 the clock and bit 1 data in, bits are sampled on rising clock edges
+    // Returns whether a word of storage changed
+    pub fn write_pins(&mut self, storage: &mut [u8], value: u8) -> bool {
+        let chip_select: bool = value & 0b10000000 != 0;
+        let clock: bool = value & 0b01000000 != 0;
+        let data_in: bool = value & 0b00000010 != 0;
+        let is_rising_edge: bool = clock && !self.clock;
+        self.chip_select = chip_select;
+        self.clock = clock;
+        self.data_in = data_in;
+
+        if !chip_select {
+            // Writes complete instantly, so the chip always reports ready
+            self.state = EepromState::Idle;
+            self.data_out = true;
+            return false;
+        }
+        if !is_rising_edge {
+            return false;
+        }
+
+        let mut is_changed: bool = false;
+        self.state = match self.state {
+            EepromState::Idle if data_in => EepromState::Command { bits: 0, count: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { bits, count } => {
+                let bits: u16 = (bits << 1) | data_in as u16;
+                if count + 1 < 10 {
+                    EepromState::Command {
+                        bits,
+                        count: count + 1,
+                    }
+                } else {
+                    let state: EepromState;
+                    (state, is_changed) = self.execute_command(storage, bits);
+                    state
+                }
+            }
+            EepromState::Reading { data, count } => {
+                self.data_out = data & 0x8000 != 0;
+                if count + 1 < 16 {
+                    EepromState::Reading {
+                        data: data << 1,
+                        count: count + 1,
+                    }
+                } else {
+                    EepromState::Done
+                }
+            }
+            EepromState::Writing {
+                address,
+                data,
+                count,
+            } => {
+                let data: u16 = (data << 1) | data_in as u16;
+                if count + 1 < 16 {
+                    EepromState::Writing {
+                        address,
+                        data,
+                        count: count + 1,
+                    }
+                } else {
+                    is_changed = match address {
+                        Some(address) => self.set_word(storage, address, data),
+                        None => self.set_all_words(storage, data),
+                    };
+                    EepromState::Done
+                }
+            }
+            EepromState::Done => EepromState::Done,
+        };
+        is_changed
+    }
+
+    // Returns the next state and whether a word of storage changed
+    fn execute_command(&mut self, storage: &mut [u8], bits: u16) -> (EepromState, bool) {
+        let op_code: u16 = bits >> 8;
+        // The most significant address bit is unused with 128 words
+        let address: u8 = (bits & 0x7F) as u8;
+        match (op_code, (bits >> 6) & 0b11) {
+            // READ starts with a dummy 0 bit
+            (0b10, _) => {
+                self.data_out = false;
+                let state: EepromState = EepromState::Reading {
+                    data: Eeprom::get_word(storage, address),
+                    count: 0,
+                };
+                (state, false)
+            }
+            // WRITE
+            (0b01, _) => {
+                let state: EepromState = EepromState::Writing {
+                    address: Some(address),
+                    data: 0,
+                    count: 0,
+                };
+                (state, false)
+            }
+            // ERASE
+            (0b11, _) => (EepromState::Done, self.set_word(storage, address, 0xFFFF)),
+            // EWDS
+            (_, 0b00) => {
+                self.is_write_enabled = false;
+                (EepromState::Done, false)
+            }
+            // WRAL
+            (_, 0b01) => {
+                let state: EepromState = EepromState::Writing {
+                    address: None,
+                    data: 0,
+                    count: 0,
+                };
+                (state, false)
+            }
+            // ERAL
+            (_, 0b10) => (EepromState::Done, self.set_all_words(storage, 0xFFFF)),
+            // EWEN
+            _ => {
+                self.is_write_enabled = true;
+                (EepromState::Done, false)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc7 {
+    // Both 0x0A at 0x0000-0x1FFF and 0x40 at 0x4000-0x5FFF are needed to map the registers
+    pub is_ram_enabled: bool,
+    pub is_ram_enabled_2: bool,
+    pub rom_bank: u8,
+    // Tilt set by the host, in g
+    pub tilt: (f32, f32),
+    pub latched_x: u16,
+    pub latched_y: u16,
+    pub eeprom: Eeprom,
+}
+
+impl Default for Mbc7 {
+    fn default() -> Self {
+        Mbc7 {
+            is_ram_enabled: false,
+            is_ram_enabled_2: false,
+            rom_bank: 0x01,
+            tilt: (0.0, 0.0),
+            latched_x: ACCELEROMETER_ERASED,
+            latched_y: ACCELEROMETER_ERASED,
+            eeprom: Eeprom::default(),
+        }
+    }
+}
+
+impl Mbc7 {
+    fn are_registers_mapped(&self, memory_address: u16) -> bool {
+        self.is_ram_enabled && self.is_ram_enabled_2 && memory_address < 0xB000
+    }
+}
+
+impl Mapper for Mbc7 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => self.is_ram_enabled = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0b01111111,
+            0x4000..=0x5FFF => self.is_ram_enabled_2 = value == 0x40,
+            _ => {}
+        }
+    }
+
+    // Registers are selected by bits 4-7 of the address and mirrored across 0xA000-0xAFFF
+    fn read_ram(&self, _ram: &[u8], memory_address: u16) -> u8 {
+        if !self.are_registers_mapped(memory_address) {
+            return 0xFF;
+        }
+        match (memory_address >> 4) & 0x0F {
+            0x2 => self.latched_x as u8,
+            0x3 => (self.latched_x >> 8) as u8,
+            0x4 => self.latched_y as u8,
+            0x5 => (self.latched_y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read_pins(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.are_registers_mapped(memory_address) {
+            return false;
+        }
+        match (memory_address >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.latched_x = ACCELEROMETER_ERASED;
+                self.latched_y = ACCELEROMETER_ERASED;
+            }
+            // Latching only works once the previous values were erased
+            0x1 if value == 0xAA && self.latched_x == ACCELEROMETER_ERASED => {
+                let (x, y): (f32, f32) = self.tilt;
+                self.latched_x = (ACCELEROMETER_CENTER + x * ACCELEROMETER_ONE_G) as u16;
+                self.latched_y = (ACCELEROMETER_CENTER + y * ACCELEROMETER_ONE_G) as u16;
+            }
+            0x8 => return self.eeprom.write_pins(ram, value),
+            _ => {}
+        }
+        false
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_mbc7() -> Mbc7 {
+        let mut mbc7: Mbc7 = Mbc7::default();
+        mbc7.write_rom(0x0000, 0x0A);
+        mbc7.write_rom(0x4000, 0x40);
+        mbc7
+    }
+
+    // Start bit followed by the op code and the address
+    fn command(op_code: u32, address: u32) -> u32 {
+        (1 << 10) | (op_code << 8) | address
+    }
+
+    // Clocks bits into the EEPROM with chip select held high
+    fn send_bits(mbc7: &mut Mbc7, eeprom: &mut [u8], bits: u32, count: u8) {
+        for bit in (0..count).rev() {
+            let data_in: u8 = (((bits >> bit) & 1) as u8) << 1;
+            mbc7.write_ram(eeprom, 0xA080, 0b10000000 | data_in);
+            mbc7.write_ram(eeprom, 0xA080, 0b11000000 | data_in);
+        }
+    }
+
+    fn receive_word(mbc7: &mut Mbc7, eeprom: &mut [u8]) -> u16 {
+        let mut word: u16 = 0;
+        for _ in 0..16 {
+            mbc7.write_ram(eeprom, 0xA080, 0b10000000);
+            mbc7.write_ram(eeprom, 0xA080, 0b11000000);
+            word = (word << 1) | (mbc7.read_ram(eeprom, 0xA080) & 1) as u16;
+        }
+        word
+    }
+
+    fn deselect(mbc7: &mut Mbc7, eeprom: &mut [u8]) {
+        mbc7.write_ram(eeprom, 0xA080, 0b00000000);
+    }
+
+    #[test]
+    fn accelerometer_latches_the_host_tilt_after_an_erase() {
+        let mut mbc7: Mbc7 = enabled_mbc7();
+        mbc7.set_tilt(1.0, -0.5);
+
+        // Not erased yet
+        mbc7.write_ram(&mut [], 0xA010, 0xAA);
+        mbc7.write_ram(&mut [], 0xA000, 0x55);
+        assert_eq!(mbc7.read_ram(&[], 0xA030), 0x80);
+        mbc7.write_ram(&mut [], 0xA010, 0xAA);
+
+        let x: u16 = ((mbc7.read_ram(&[], 0xA030) as u16) << 8) | mbc7.read_ram(&[], 0xA020) as u16;
+        let y: u16 = ((mbc7.read_ram(&[], 0xA050) as u16) << 8) | mbc7.read_ram(&[], 0xA040) as u16;
+        assert_eq!(x, 0x81D0 + 0x70);
+        assert_eq!(y, 0x81D0 - 0x38);
+
+        // Registers need both enables
+        mbc7.write_rom(0x4000, 0x00);
+        assert_eq!(mbc7.read_ram(&[], 0xA020), 0xFF);
+    }
+
+    #[test]
+    fn eeprom_reads_and_writes_words() {
+        let mut eeprom: Vec<u8> = vec![0xFF; EEPROM_SIZE];
+        let mut mbc7: Mbc7 = enabled_mbc7();
+
+        // WRITE is ignored until EWEN
+        send_bits(&mut mbc7, &mut eeprom, command(0b01, 0b00000101), 11);
+        send_bits(&mut mbc7, &mut eeprom, 0x1234, 16);
+        deselect(&mut mbc7, &mut eeprom);
+        assert_eq!(eeprom[10], 0xFF);
+
+        send_bits(&mut mbc7, &mut eeprom, command(0b00, 0b11000000), 11);
+        deselect(&mut mbc7, &mut eeprom);
+        send_bits(&mut mbc7, &mut eeprom, command(0b01, 0b00000101), 11);
+        send_bits(&mut mbc7, &mut eeprom, 0x1234, 16);
+        deselect(&mut mbc7, &mut eeprom);
+        assert_eq!(eeprom[10..12], [0x34, 0x12]);
+        assert_eq!(mbc7.read_ram(&eeprom, 0xA080) & 1, 1);
+
+        // READ outputs a dummy 0 bit before the word
+        send_bits(&mut mbc7, &mut eeprom, command(0b10, 0b00000101), 11);
+        assert_eq!(mbc7.read_ram(&eeprom, 0xA080) & 1, 0);
+        assert_eq!(receive_word(&mut mbc7, &mut eeprom), 0x1234);
+        deselect(&mut mbc7, &mut eeprom);
+
+        // ERAL then WRAL
+        send_bits(&mut mbc7, &mut eeprom, command(0b00, 0b10000000), 11);
+        deselect(&mut mbc7, &mut eeprom);
+        assert_eq!(eeprom[10..12], [0xFF, 0xFF]);
+        send_bits(&mut mbc7, &mut eeprom, command(0b00, 0b01000000), 11);
+        send_bits(&mut mbc7, &mut eeprom, 0xABCD, 16);
+        deselect(&mut mbc7, &mut eeprom);
+        assert!(eeprom.chunks(2).all(|word| word == [0xCD, 0xAB]));
+    }
+}
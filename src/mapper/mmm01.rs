@@ -0,0 +1,168 @@
+// MMM01 behaviour is based on https://gbdev.io/pandocs/MMM01.html
+// Multiplexing the RAM and ROM bank bits is not emulated
+use crate::mapper::{self, Mapper, RAM_BANK_SIZE};
+use crate::memory::ROM_BANK_SIZE;
+
+// The menu and its header sit in the last 32 KiB of the ROM
+pub const MENU_SIZE: usize = 2 * ROM_BANK_SIZE;
+
+// Starts unmapped with the menu visible, the menu then configures the bank bits of a game and maps
+// it, locking the configuration until the next reset
+#[derive(Debug, Default)]
+pub struct Mmm01 {
+    pub is_mapped: bool,
+    pub is_ram_enabled: bool,
+    pub rom_bank_low: u8,  // 5 bits
+    pub rom_bank_mid: u8,  // 2 bits, set while unmapped
+    pub rom_bank_high: u8, // 2 bits, set while unmapped
+    pub rom_bank_mask: u8, // 4 bits, bits 1-4 of rom_bank_low the game cannot change
+    pub ram_bank_low: u8,  // 2 bits
+    pub ram_bank_high: u8, // 2 bits, set while unmapped
+    pub ram_bank_mask: u8, // 2 bits, bits of ram_bank_low the game cannot change
+    pub is_advanced_mode: bool,
+    pub is_mode_locked: bool,
+}
+
+impl Mmm01 {
+    fn get_rom_bank_fixed_bits(&self) -> u8 {
+        if self.is_mapped {
+            self.rom_bank_mask << 1
+        } else {
+            0
+        }
+    }
+
+    fn get_ram_bank_fixed_bits(&self) -> u8 {
+        if self.is_mapped {
+            self.ram_bank_mask
+        } else {
+            0
+        }
+    }
+
+    fn get_game_first_bank(&self) -> usize {
+        ((self.rom_bank_high as usize) << 7) | ((self.rom_bank_mid as usize) << 5)
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        if !self.is_mapped {
+            let offset: usize = rom.len().saturating_sub(MENU_SIZE) + memory_address as usize;
+            return rom.get(offset).copied().unwrap_or(0xFF);
+        }
+
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            self.get_game_first_bank()
+                | (self.rom_bank_low & self.get_rom_bank_fixed_bits()) as usize
+        } else {
+            let rom_bank_low: u8 = match self.rom_bank_low {
+                0 => 1,
+                rom_bank_low => rom_bank_low,
+            };
+            self.get_game_first_bank() | rom_bank_low as usize
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF => {
+                self.is_ram_enabled = value & 0b00001111 == 0x0A;
+                if !self.is_mapped {
+                    self.ram_bank_mask = (value >> 4) & 0b00000011;
+                    self.is_mapped = value & 0b01000000 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                let fixed_bits: u8 = self.get_rom_bank_fixed_bits();
+                self.rom_bank_low =
+                    (self.rom_bank_low & fixed_bits) | (value & 0b00011111 & !fixed_bits);
+                if !self.is_mapped {
+                    self.rom_bank_mid = (value >> 5) & 0b00000011;
+                }
+            }
+            0x4000..=0x5FFF => {
+                let fixed_bits: u8 = self.get_ram_bank_fixed_bits();
+                self.ram_bank_low =
+                    (self.ram_bank_low & fixed_bits) | (value & 0b00000011 & !fixed_bits);
+                if !self.is_mapped {
+                    self.ram_bank_high = (value >> 2) & 0b00000011;
+                    self.rom_bank_high = (value >> 4) & 0b00000011;
+                    self.is_mode_locked = value & 0b01000000 != 0;
+                }
+            }
+            _ => {
+                if !self.is_mode_locked {
+                    self.is_advanced_mode = value & 0b00000001 != 0;
+                }
+                if !self.is_mapped {
+                    self.rom_bank_mask = (value >> 2) & 0b00001111;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], memory_address: u16) -> u8 {
+        if !self.is_ram_enabled {
+            return 0xFF;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::get_banked_value(ram, self.get_ram_bank(), RAM_BANK_SIZE, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if !self.is_ram_enabled {
+            return false;
+        }
+        let offset: usize = (memory_address - 0xA000) as usize;
+        mapper::set_banked_value(ram, self.get_ram_bank(), RAM_BANK_SIZE, offset, value)
+    }
+}
+
+impl Mmm01 {
+    // Like MBC1, mode 0 keeps the bits the game controls at 0
+    fn get_ram_bank(&self) -> usize {
+        let ram_bank_low: u8 = if self.is_advanced_mode {
+            self.ram_bank_low
+        } else {
+            self.ram_bank_low & self.get_ram_bank_fixed_bits()
+        };
+        ((self.ram_bank_high << 2) | ram_bank_low) as usize
+    }
+}
+
+// The menu header in the last 32 KiB declares an MMM01 cartridge type
+pub fn is_mmm01(rom: &[u8]) -> bool {
+    rom.len() > MENU_SIZE
+        && rom.len().is_power_of_two()
+        && matches!(rom[rom.len() - MENU_SIZE + 0x0147], 0x0B..=0x0D)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn menu_maps_a_game_and_locks_its_configuration() {
+        let rom: Vec<u8> = numbered_rom(64, ROM_BANK_SIZE);
+        let mut mmm01: Mmm01 = Mmm01::default();
+        assert_eq!(mmm01.read_rom(&rom, 0x0000), 62);
+        assert_eq!(mmm01.read_rom(&rom, 0x4000), 63);
+
+        // Game in banks 0x20-0x27: mid bits 01 and bank bits 3-4 fixed to 0
+        mmm01.write_rom(0x2000, 0b00100000);
+        mmm01.write_rom(0x6000, 0b00110000);
+        mmm01.write_rom(0x0000, 0b01000000);
+        assert!(mmm01.is_mapped);
+        assert_eq!(mmm01.read_rom(&rom, 0x0000), 0x20);
+        assert_eq!(mmm01.read_rom(&rom, 0x4000), 0x21);
+
+        mmm01.write_rom(0x2000, 0b01111111);
+        assert_eq!(mmm01.read_rom(&rom, 0x4000), 0x27);
+        mmm01.write_rom(0x4000, 0b00110000);
+        assert_eq!(mmm01.read_rom(&rom, 0x4000), 0x27);
+    }
+}
@@ -0,0 +1,155 @@
+// Sachen mappers are only documented through reverse engineering, as done by mGBA
+// To pass the boot ROM logo check, reads of 0x0100-0x01FF are redirected to the copy of the
+// Nintendo logo at 0x0180-0x01FF until the logo was read, bit swapped on MMC2
+use std::cell::Cell;
+
+use crate::mapper::{self, Mapper};
+use crate::memory::ROM_BANK_SIZE;
+
+// Reads of the header page needed by the boot ROM logo check
+const LOCKED_READS: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SachenVariant {
+    Mmc1,
+    Mmc2,
+}
+
+#[derive(Debug)]
+pub struct Sachen {
+    pub variant: SachenVariant,
+    // Multicart menus set these until bits 4 and 5 of the base bank get cleared
+    pub base_bank: u8,
+    pub bank_mask: u8,
+    pub rom_bank: u8,
+    pub locked_reads: Cell<u8>,
+}
+
+impl Sachen {
+    pub fn new(variant: SachenVariant) -> Sachen {
+        Sachen {
+            variant,
+            base_bank: 0xFF,
+            bank_mask: 0x00,
+            rom_bank: 0x01,
+            locked_reads: Cell::new(0),
+        }
+    }
+
+    fn is_menu_mode(&self) -> bool {
+        self.base_bank & 0b00110000 == 0b00110000
+    }
+
+    // Swaps address bits 0 and 6, and 1 and 4
+    pub fn unscramble_address(memory_address: u16) -> u16 {
+        (memory_address & 0xFFAC)
+            | ((memory_address & 0x0040) >> 6)
+            | ((memory_address & 0x0010) >> 3)
+            | ((memory_address & 0x0002) << 3)
+            | ((memory_address & 0x0001) << 6)
+    }
+
+    // Address the locked mapper really reads for memory_address
+    pub fn get_locked_address(variant: SachenVariant, memory_address: u16) -> u16 {
+        match variant {
+            SachenVariant::Mmc1 => memory_address | 0x0080,
+            SachenVariant::Mmc2 => Sachen::unscramble_address(memory_address | 0x0080),
+        }
+    }
+}
+
+impl Mapper for Sachen {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let mut memory_address: u16 = memory_address;
+        if memory_address & 0xFF00 == 0x0100 && self.locked_reads.get() < LOCKED_READS {
+            self.locked_reads.set(self.locked_reads.get() + 1);
+            memory_address = Sachen::get_locked_address(self.variant, memory_address);
+        }
+
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            self.base_bank & self.bank_mask
+        } else {
+            (self.rom_bank & !self.bank_mask) | (self.base_bank & self.bank_mask)
+        } as usize;
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, memory_address: u16, value: u8) {
+        match memory_address {
+            0x0000..=0x1FFF if self.is_menu_mode() => self.base_bank = value,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value {
+                    0 => 1,
+                    rom_bank => rom_bank,
+                }
+            }
+            0x4000..=0x5FFF if self.is_menu_mode() => self.bank_mask = value,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, _ram: &[u8], _memory_address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _ram: &mut [u8], _memory_address: u16, _value: u8) -> bool {
+        false
+    }
+
+    fn finish_boot(&mut self) {
+        self.locked_reads.set(LOCKED_READS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn logo_reads_are_redirected_until_unlocked() {
+        let mut rom: Vec<u8> = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x0104] = 0x11;
+        rom[0x0184] = 0x22;
+        rom[0x0150] = 0x33;
+        rom[0x0181] = 0x44;
+
+        let mmc1: Sachen = Sachen::new(SachenVariant::Mmc1);
+        assert_eq!(mmc1.read_rom(&rom, 0x0104), 0x22);
+        for _ in 1..LOCKED_READS {
+            mmc1.read_rom(&rom, 0x0150);
+        }
+        assert_eq!(mmc1.read_rom(&rom, 0x0104), 0x11);
+        assert_eq!(mmc1.read_rom(&rom, 0x0150), 0x33);
+
+        // Address bits 0 and 6 swap, so does 1 with 4
+        assert_eq!(Sachen::unscramble_address(0x0150), 0x0103);
+        let mmc2: Sachen = Sachen::new(SachenVariant::Mmc2);
+        assert_eq!(mmc2.read_rom(&rom, 0x0140), 0x44);
+
+        let mut post_boot: Sachen = Sachen::new(SachenVariant::Mmc2);
+        post_boot.finish_boot();
+        assert_eq!(post_boot.read_rom(&rom, 0x0104), 0x11);
+    }
+
+    #[test]
+    fn menu_mode_sets_the_base_bank_and_mask() {
+        let rom: Vec<u8> = numbered_rom(16, ROM_BANK_SIZE);
+        let mut sachen: Sachen = Sachen::new(SachenVariant::Mmc1);
+        sachen.finish_boot();
+        assert_eq!(sachen.read_rom(&rom, 0x4000), 0x01);
+
+        // Game in banks 8-11
+        sachen.write_rom(0x4000, 0b00001100);
+        sachen.write_rom(0x0000, 0b00001000);
+        assert_eq!(sachen.read_rom(&rom, 0x0000), 0x08);
+        sachen.write_rom(0x2000, 0x03);
+        assert_eq!(sachen.read_rom(&rom, 0x4000), 0x0B);
+
+        // Leaving menu mode locks the base bank and mask
+        sachen.write_rom(0x0000, 0b00000000);
+        sachen.write_rom(0x4000, 0b00000000);
+        assert_eq!(sachen.read_rom(&rom, 0x4000), 0x0B);
+    }
+}
@@ -0,0 +1,241 @@
+// TAMA5 is only documented through reverse engineering, see https://gbdev.io/pandocs/MBCs.html
+// Everything goes through a data port at 0xA000 and a register select port at 0xA001
+use crate::mapper::{self, CartridgeClock, ClockSource, Mapper, SECONDS_PER_DAY};
+use crate::memory::ROM_BANK_SIZE;
+
+// 32 bytes of RAM inside the mapper
+pub const RAM_SIZE: usize = 0x20;
+
+// TC8521 RTC keeping the date as BCD digits, registers 0x0-0xC:
+// seconds, tens of seconds, minutes, tens of minutes, hours, tens of hours, day of the week,
+// day, tens of days, month, tens of months, year, tens of years
+#[derive(Debug)]
+pub struct Tc8521 {
+    pub clock: CartridgeClock,
+    pub digits: [u8; 13],
+}
+
+impl Tc8521 {
+    pub fn new(clock_source: ClockSource) -> Tc8521 {
+        // Saturday 1 January 2000 at midnight
+        let mut digits: [u8; 13] = [0; 13];
+        digits[6] = 6;
+        digits[7] = 1;
+        digits[9] = 1;
+        Tc8521 {
+            clock: CartridgeClock::new(clock_source),
+            digits,
+        }
+    }
+
+    fn get_value(&self, index: usize) -> u64 {
+        (self.digits[index] + 10 * self.digits[index + 1]) as u64
+    }
+
+    fn set_value(&mut self, index: usize, value: u64) {
+        self.digits[index] = (value % 10) as u8;
+        self.digits[index + 1] = (value / 10 % 10) as u8;
+    }
+
+    fn get_days_in_month(month: u64, year: u64) -> u64 {
+        match month {
+            2 if year.is_multiple_of(4) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    pub fn update(&mut self) {
+        let elapsed_seconds: u64 = self.clock.take_elapsed_seconds();
+        if elapsed_seconds == 0 {
+            return;
+        }
+
+        let time_of_day: u64 =
+            self.get_value(0) + 60 * self.get_value(2) + 3600 * self.get_value(4) + elapsed_seconds;
+        let time: u64 = time_of_day % SECONDS_PER_DAY;
+        self.set_value(0, time % 60);
+        self.set_value(2, time / 60 % 60);
+        self.set_value(4, time / 3600);
+
+        let (mut day, mut month, mut year): (u64, u64, u64) =
+            (self.get_value(7), self.get_value(9), self.get_value(11));
+        for _ in 0..time_of_day / SECONDS_PER_DAY {
+            self.digits[6] = (self.digits[6] + 1) % 7;
+            day += 1;
+            if day > Tc8521::get_days_in_month(month, year) {
+                day = 1;
+                month += 1;
+                if month > 12 {
+                    month = 1;
+                    year = (year + 1) % 100;
+                }
+            }
+        }
+        self.set_value(7, day);
+        self.set_value(9, month);
+        self.set_value(11, year);
+    }
+
+    pub fn read_register(&mut self, register: u8) -> u8 {
+        self.update();
+        self.digits.get(register as usize).copied().unwrap_or(0x0)
+    }
+
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        self.update();
+        if let Some(digit) = self.digits.get_mut(register as usize) {
+            *digit = value & 0b00001111;
+            // Setting the seconds restarts the current second
+            if register == 0x0 {
+                self.clock.reset_sub_second()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tama5 {
+    pub selected_register: u8,
+    // 4 bit registers written through 0xA000
+    // 0x0-0x1: ROM bank, 0x4-0x5: data, 0x6: command and address msb, 0x7: address
+    pub registers: [u8; 0x10],
+    // Byte read by the last command, exposed through registers 0xC and 0xD
+    pub result: u8,
+    pub rtc: Tc8521,
+}
+
+impl Tama5 {
+    pub fn new(clock_source: ClockSource) -> Tama5 {
+        Tama5 {
+            selected_register: 0x00,
+            registers: [0; 0x10],
+            result: 0x00,
+            rtc: Tc8521::new(clock_source),
+        }
+    }
+
+    fn get_rom_bank(&self) -> usize {
+        (self.registers[0x0] | ((self.registers[0x1] & 0b00000001) << 4)) as usize
+    }
+
+    // Runs once the address is complete, returns whether a byte of ram changed
+    // Commands are 0: RAM write, 1: RAM read, 2: RTC write and 3: RTC read
+    fn execute(&mut self, ram: &mut [u8]) -> bool {
+        let command: u8 = self.registers[0x6] >> 1;
+        let address: u8 = ((self.registers[0x6] & 0b00000001) << 4) | self.registers[0x7];
+        let data: u8 = (self.registers[0x5] << 4) | self.registers[0x4];
+        match command {
+            0 => return mapper::set_banked_value(ram, 0, RAM_SIZE, address as usize, data),
+            1 => self.result = mapper::get_banked_value(ram, 0, RAM_SIZE, address as usize),
+            2 => self.rtc.write_register(address & 0x0F, data),
+            3 => self.result = self.rtc.read_register(address & 0x0F),
+            _ => {}
+        }
+        false
+    }
+}
+
+impl Mapper for Tama5 {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        let offset: usize = (memory_address as usize) % ROM_BANK_SIZE;
+        let bank: usize = if memory_address < 0x4000 {
+            0
+        } else {
+            self.get_rom_bank()
+        };
+        mapper::get_banked_value(rom, bank, ROM_BANK_SIZE, offset)
+    }
+
+    fn write_rom(&mut self, _memory_address: u16, _value: u8) {}
+
+    fn read_ram(&self, _ram: &[u8], memory_address: u16) -> u8 {
+        if memory_address & 0b00000001 != 0 {
+            return 0xFF;
+        }
+        match self.selected_register {
+            // Games wait for this to read 1 before talking to the mapper
+            0xA => 0xF1,
+            0xC => 0xF0 | (self.result & 0b00001111),
+            0xD => 0xF0 | (self.result >> 4),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], memory_address: u16, value: u8) -> bool {
+        if memory_address & 0b00000001 != 0 {
+            self.selected_register = value & 0b00001111;
+            return false;
+        }
+        self.registers[self.selected_register as usize] = value & 0b00001111;
+        self.selected_register == 0x7 && self.execute(ram)
+    }
+
+    fn advance(&mut self, m_cycles: u64) {
+        if self.rtc.clock.advance(m_cycles) {
+            self.rtc.update()
+        }
+    }
+
+    // The clock is left out of saves, no footer layout is shared between emulators for TAMA5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    fn write_register(tama5: &mut Tama5, ram: &mut [u8], register: u8, value: u8) {
+        tama5.write_ram(ram, 0xA001, register);
+        tama5.write_ram(ram, 0xA000, value);
+    }
+
+    fn run_command(tama5: &mut Tama5, ram: &mut [u8], command: u8, address: u8, data: u8) -> u8 {
+        write_register(tama5, ram, 0x4, data & 0x0F);
+        write_register(tama5, ram, 0x5, data >> 4);
+        write_register(tama5, ram, 0x6, (command << 1) | (address >> 4));
+        write_register(tama5, ram, 0x7, address & 0x0F);
+        tama5.write_ram(ram, 0xA001, 0xD);
+        let high: u8 = tama5.read_ram(ram, 0xA000) & 0x0F;
+        tama5.write_ram(ram, 0xA001, 0xC);
+        (high << 4) | (tama5.read_ram(ram, 0xA000) & 0x0F)
+    }
+
+    #[test]
+    fn registers_bank_the_rom_and_access_the_ram() {
+        let rom: Vec<u8> = numbered_rom(32, ROM_BANK_SIZE);
+        let mut ram: Vec<u8> = vec![0; RAM_SIZE];
+        let mut tama5: Tama5 = Tama5::new(ClockSource::Cycles);
+
+        tama5.write_ram(&mut ram, 0xA001, 0xA);
+        assert_eq!(tama5.read_ram(&ram, 0xA000), 0xF1);
+
+        write_register(&mut tama5, &mut ram, 0x0, 0x3);
+        write_register(&mut tama5, &mut ram, 0x1, 0x1);
+        assert_eq!(tama5.read_rom(&rom, 0x4000), 0x13);
+
+        run_command(&mut tama5, &mut ram, 0, 0x1F, 0xA5);
+        assert_eq!(ram[0x1F], 0xA5);
+        assert_eq!(run_command(&mut tama5, &mut ram, 1, 0x1F, 0x00), 0xA5);
+    }
+
+    #[test]
+    fn rtc_rolls_over_the_end_of_february() {
+        let mut ram: Vec<u8> = vec![0; RAM_SIZE];
+        let mut tama5: Tama5 = Tama5::new(ClockSource::Cycles);
+
+        // 28 February 2001 at 23:59:59
+        for (register, digit) in [9, 5, 9, 5, 3, 2, 0, 8, 2, 2, 0, 1, 0]
+            .into_iter()
+            .enumerate()
+        {
+            run_command(&mut tama5, &mut ram, 2, register as u8, digit);
+        }
+        tama5.rtc.clock.elapsed_seconds = 1;
+        let digits: Vec<u8> = (0..13)
+            .map(|register| run_command(&mut tama5, &mut ram, 3, register, 0x00))
+            .collect();
+        assert_eq!(digits, [0, 0, 0, 0, 0, 0, 1, 1, 0, 3, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,64 @@
+// Wisdom Tree carts switch the whole 0x0000-0x7FFF area by 32 KiB at once
+// The bank number is the low byte of the address written to, the value is ignored
+use crate::mapper::{self, Mapper};
+
+const BANK_SIZE: usize = 0x8000;
+
+#[derive(Debug, Default)]
+pub struct WisdomTree {
+    pub rom_bank: u8,
+}
+
+impl Mapper for WisdomTree {
+    fn read_rom(&self, rom: &[u8], memory_address: u16) -> u8 {
+        mapper::get_banked_value(
+            rom,
+            self.rom_bank as usize,
+            BANK_SIZE,
+            memory_address as usize,
+        )
+    }
+
+    fn write_rom(&mut self, memory_address: u16, _value: u8) {
+        if memory_address < 0x4000 {
+            self.rom_bank = memory_address as u8
+        }
+    }
+
+    fn read_ram(&self, _ram: &[u8], _memory_address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _ram: &mut [u8], _memory_address: u16, _value: u8) -> bool {
+        false
+    }
+}
+
+// A header without mapper on a ROM too large for it, with the publisher name in the first bank
+pub fn is_wisdom_tree(rom: &[u8]) -> bool {
+    rom.len() > BANK_SIZE
+        && rom.len().is_power_of_two()
+        && rom[..BANK_SIZE]
+            .windows(11)
+            .any(|bytes| bytes == b"WISDOM TREE" || bytes == b"WISDOM\0TREE")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::tests::numbered_rom;
+
+    #[test]
+    fn write_address_selects_a_32_kib_bank() {
+        let rom: Vec<u8> = numbered_rom(4, BANK_SIZE);
+        let mut wisdom_tree: WisdomTree = WisdomTree::default();
+
+        wisdom_tree.write_rom(0x0002, 0xFF);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x0000), 0x02);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x7FFF), 0x02);
+        wisdom_tree.write_rom(0x1F01, 0x00);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x4000), 0x01);
+        wisdom_tree.write_rom(0x4003, 0x00);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x4000), 0x01);
+    }
+}
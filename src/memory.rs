@@ -1,18 +1,56 @@
+// Memory map and I/O read-back values are based on https://gbdev.io/pandocs/Memory_Map.html
 use std::fmt;
 
+use crate::boot_rom::BootRom;
+use crate::cartridge::Cartridge;
+use crate::error::EmulatorError;
+use crate::interrupts::InterruptController;
+use crate::mapper::{self, ClockSource, Mapper, NoMapper};
+use crate::model::Model;
+use crate::scheduler::{Event, Scheduler};
+use crate::timer::Timer;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const VRAM_BANK_SIZE: usize = 0x2000;
+pub const WRAM_BANK_SIZE: usize = 0x1000;
+
+const VRAM_BANK_ADDRESS: u16 = 0xFF4F; // VBK, CGB only
+const WRAM_BANK_ADDRESS: u16 = 0xFF70; // SVBK, CGB only
+
 pub struct Memory {
-    pub memory: [u8; 8192],
+    pub model: Model,
+    pub rom: Vec<u8>,          // Cartridge ROM from 0x0000 to 0x7FFF
+    pub vram: Vec<u8>,         // 1 bank on DMG, 2 banks on CGB
+    pub external_ram: Vec<u8>, // Cartridge RAM from 0xA000 to 0xBFFF, empty without cartridge RAM
+    pub wram: Vec<u8>,         // 2 banks on DMG, 8 banks on CGB
+    pub oam: [u8; 0xA0],
+    pub io_registers: [u8; 0x80],
+    pub hram: [u8; 0x7F],
+    pub interrupts: InterruptController,
+    pub timer: Timer,
+    pub scheduler: Scheduler,
+    // CPU M-cycle count passed with the last tick, never advanced by the memory itself
+    pub cpu_cycle_counter: u64,
+    // CPU M-cycle counts the timer and the mapper were last brought up to date at
+    pub timer_cycle: u64,
+    pub mapper_cycle: u64,
+    pub boot_rom: Option<BootRom>,
+    pub mapper: Box<dyn Mapper>,
+    // Set by writes to the cartridge RAM area, cleared once the RAM is saved
+    pub is_external_ram_dirty: bool,
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        Self { memory: [0; 8192] } // Initialize all bytes to 0
+        Memory::new(Model::default())
     }
 }
 
 impl fmt::Debug for Memory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (address, &content) in self.memory.iter().enumerate() {
+        // Only the work RAM and the high RAM are dumped
+        for address in (0xC000..=0xDFFF).chain(0xFF80..=0xFFFE) {
+            let content: u8 = self.get_value_at_memory_address(address);
             write!(f, "{:04X}: {:08b}\t", address, content)?;
         }
         Ok(())
@@ -20,11 +58,389 @@ impl fmt::Debug for Memory {
 }
 
 impl Memory {
+    pub fn new(model: Model) -> Memory {
+        let (vram_banks, wram_banks): (usize, usize) = if model.is_cgb() { (2, 8) } else { (1, 2) };
+        Memory {
+            model,
+            rom: Vec::new(),
+            vram: vec![0; vram_banks * VRAM_BANK_SIZE],
+            external_ram: Vec::new(),
+            wram: vec![0; wram_banks * WRAM_BANK_SIZE],
+            oam: [0; 0xA0],
+            io_registers: [0; 0x80],
+            hram: [0; 0x7F],
+            interrupts: InterruptController::default(),
+            timer: Timer::default(),
+            scheduler: Scheduler::default(),
+            cpu_cycle_counter: 0,
+            timer_cycle: 0,
+            mapper_cycle: 0,
+            boot_rom: None,
+            mapper: Box::new(NoMapper),
+            is_external_ram_dirty: false,
+        }
+    }
+
+    fn get_vram_index(&self, memory_address: u16) -> usize {
+        let bank: usize = if self.model.is_cgb() {
+            (self.io_registers[(VRAM_BANK_ADDRESS - 0xFF00) as usize] & 0b00000001) as usize
+        } else {
+            0
+        };
+        bank * VRAM_BANK_SIZE + (memory_address - 0x8000) as usize
+    }
+
+    // 0xC000-0xCFFF is always bank 0, 0xD000-0xDFFF is bank 1 or the CGB bank selected by SVBK
+    fn get_wram_index(&self, memory_address: u16) -> usize {
+        let offset: usize = (memory_address & 0x1FFF) as usize;
+        if offset < WRAM_BANK_SIZE {
+            return offset;
+        }
+
+        let bank: usize = if self.model.is_cgb() {
+            match self.io_registers[(WRAM_BANK_ADDRESS - 0xFF00) as usize] & 0b00000111 {
+                0 => 1,
+                bank => bank as usize,
+            }
+        } else {
+            1
+        };
+        bank * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+    }
+
+    // Bits of an I/O register that are unused and always read as 1, 0xFF for unmapped registers
+    fn get_io_register_read_mask(&self, memory_address: u16) -> u8 {
+        let is_cgb: bool = self.model.is_cgb();
+        match memory_address {
+            0xFF00 => 0b11000000,                            // P1
+            0xFF01 => 0b00000000,                            // SB
+            0xFF02 if is_cgb => 0b01111100,                  // SC
+            0xFF02 => 0b01111110,                            // SC
+            0xFF04..=0xFF06 => 0b00000000,                   // DIV, TIMA, TMA
+            0xFF07 => 0b11111000,                            // TAC
+            0xFF10 => 0b10000000,                            // NR10
+            0xFF11 | 0xFF16 => 0b00111111,                   // NR11, NR21
+            0xFF12 | 0xFF17 | 0xFF21 | 0xFF22 => 0b00000000, // NR12, NR22, NR42, NR43
+            0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => 0b10111111, // NR14, NR24, NR34, NR44
+            0xFF1A => 0b01111111,                            // NR30
+            0xFF1C => 0b10011111,                            // NR32
+            0xFF24 | 0xFF25 => 0b00000000,                   // NR50, NR51
+            0xFF26 => 0b01110000,                            // NR52
+            0xFF30..=0xFF3F => 0b00000000,                   // Wave RAM
+            0xFF40 => 0b00000000,                            // LCDC
+            0xFF41 => 0b10000000,                            // STAT
+            0xFF42..=0xFF4B => 0b00000000,                   // SCY to WX
+            0xFF4D if is_cgb => 0b01111110,                  // KEY1
+            0xFF4F if is_cgb => 0b11111110,                  // VBK
+            0xFF55 if is_cgb => 0b00000000,                  // HDMA5
+            0xFF56 if is_cgb => 0b00111100,                  // RP
+            0xFF68 | 0xFF6A if is_cgb => 0b01000000,         // BCPS, OCPS
+            0xFF69 | 0xFF6B if is_cgb => 0b00000000,         // BCPD, OCPD
+            0xFF70 if is_cgb => 0b11111000,                  // SVBK
+            // Write only and unmapped registers
+            _ => 0b11111111,
+        }
+    }
+
     pub fn get_value_at_memory_address(&self, memory_address: u16) -> u8 {
-        self.memory[memory_address as usize]
+        // The boot ROM overlays the cartridge until it gets unmapped
+        if let Some(value) = self
+            .boot_rom
+            .as_ref()
+            .and_then(|boot_rom| boot_rom.get_value_at_memory_address(memory_address))
+        {
+            return value;
+        }
+
+        match memory_address {
+            // ROM, reads without a cartridge float to 0xFF
+            0x0000..=0x7FFF => self.mapper.read_rom(&self.rom, memory_address),
+            0x8000..=0x9FFF => self.vram[self.get_vram_index(memory_address)],
+            0xA000..=0xBFFF => self.mapper.read_ram(&self.external_ram, memory_address),
+            // Echo RAM mirrors 0xC000-0xDDFF
+            0xC000..=0xFDFF => self.wram[self.get_wram_index(memory_address)],
+            0xFE00..=0xFE9F => self.oam[(memory_address - 0xFE00) as usize],
+            // Unusable region
+            0xFEA0..=0xFEFF => 0x00,
+            InterruptController::INTERRUPT_FLAG_ADDRESS => self.interrupts.read_interrupt_flag(),
+            // Nothing happens to the timer between its events but counting
+            Timer::DIV_ADDRESS..=Timer::TAC_ADDRESS => {
+                let lag: u64 = self.cpu_cycle_counter - self.timer_cycle;
+                self.timer.get_skipped(lag).read_register(memory_address)
+                    | self.get_io_register_read_mask(memory_address)
+            }
+            0xFF00..=0xFF7F => {
+                self.io_registers[(memory_address - 0xFF00) as usize]
+                    | self.get_io_register_read_mask(memory_address)
+            }
+            0xFF80..=0xFFFE => self.hram[(memory_address - 0xFF80) as usize],
+            InterruptController::INTERRUPT_ENABLE_ADDRESS => self.interrupts.interrupt_enable,
+        }
     }
 
     pub fn set_value_at_memory_address(&mut self, memory_address: u16, value: u8) {
-        self.memory[memory_address as usize] = value
+        match memory_address {
+            // Writes to the ROM are meant for the mapper
+            0x0000..=0x7FFF => {
+                self.update_mapper();
+                self.mapper.write_rom(memory_address, value)
+            }
+            0x8000..=0x9FFF => {
+                let vram_index: usize = self.get_vram_index(memory_address);
+                self.vram[vram_index] = value
+            }
+            0xA000..=0xBFFF => {
+                self.update_mapper();
+                if self
+                    .mapper
+                    .write_ram(&mut self.external_ram, memory_address, value)
+                {
+                    self.is_external_ram_dirty = true
+                }
+            }
+            0xC000..=0xFDFF => {
+                let wram_index: usize = self.get_wram_index(memory_address);
+                self.wram[wram_index] = value
+            }
+            0xFE00..=0xFE9F => self.oam[(memory_address - 0xFE00) as usize] = value,
+            0xFEA0..=0xFEFF => {}
+            BootRom::BOOT_ROM_DISABLE_ADDRESS => {
+                if let Some(boot_rom) = self.boot_rom.as_mut() {
+                    boot_rom.write_boot_rom_disable(value)
+                }
+            }
+            InterruptController::INTERRUPT_FLAG_ADDRESS => {
+                self.interrupts.write_interrupt_flag(value)
+            }
+            Timer::DIV_ADDRESS..=Timer::TAC_ADDRESS => {
+                self.update_timer();
+                self.timer.write_register(memory_address, value);
+                self.update_timer()
+            }
+            0xFF00..=0xFF7F => self.io_registers[(memory_address - 0xFF00) as usize] = value,
+            0xFF80..=0xFFFE => self.hram[(memory_address - 0xFF80) as usize] = value,
+            InterruptController::INTERRUPT_ENABLE_ADDRESS => {
+                self.interrupts.interrupt_enable = value
+            }
+        }
+    }
+
+    // Copies a program into memory starting at start_address, writing through to the ROM
+    pub fn load_program(&mut self, start_address: u16, program: &[u8]) {
+        for (offset, &value) in program.iter().enumerate() {
+            let memory_address: u16 = start_address.wrapping_add(offset as u16);
+            if memory_address < 0x8000 {
+                if self.rom.len() < 2 * ROM_BANK_SIZE {
+                    self.rom.resize(2 * ROM_BANK_SIZE, 0);
+                }
+                self.rom[memory_address as usize] = value
+            } else {
+                self.set_value_at_memory_address(memory_address, value)
+            }
+        }
+    }
+
+    // Plugs cartridge in behind its mapper, its RAM starts cleared
+    pub fn load_cartridge(
+        &mut self,
+        cartridge: Cartridge,
+        clock_source: ClockSource,
+    ) -> Result<(), EmulatorError> {
+        self.mapper = mapper::from_cartridge(&cartridge, clock_source)?;
+        self.external_ram =
+            vec![mapper::get_blank_ram_value(&cartridge); mapper::get_ram_size(&cartridge)];
+        self.rom = cartridge.rom;
+        Ok(())
+    }
+
+    // Sets the I/O registers to the values left by the boot ROM of model
+    pub fn apply_post_boot_state(&mut self, model: Model) {
+        for (memory_address, value) in model.post_boot_io_registers() {
+            self.set_value_at_memory_address(memory_address, value);
+        }
+        self.timer.divider = model.post_boot_divider();
+        self.update_timer();
+        self.mapper.finish_boot();
+    }
+
+    // Brings the timer up to the current M-cycle and schedules its next overflow or reload
+    pub fn update_timer(&mut self) {
+        let m_cycles: u64 = self.cpu_cycle_counter - self.timer_cycle;
+        self.timer.advance(m_cycles, &mut self.interrupts);
+        self.timer_cycle = self.cpu_cycle_counter;
+        match self.timer.get_m_cycles_until_event() {
+            Some(m_cycles) => self
+                .scheduler
+                .schedule(Event::Timer, self.cpu_cycle_counter + m_cycles),
+            None => self.scheduler.cancel(Event::Timer),
+        }
+    }
+
+    pub fn update_mapper(&mut self) {
+        self.mapper
+            .advance(self.cpu_cycle_counter - self.mapper_cycle);
+        self.mapper_cycle = self.cpu_cycle_counter;
+    }
+
+    // Catches up with the CPU M-cycle count and runs the events that became due
+    pub fn advance_to(&mut self, cycle_counter: u64) {
+        self.cpu_cycle_counter = cycle_counter;
+        if !self.scheduler.is_due(cycle_counter) {
+            return;
+        }
+        while let Some(event) = self.scheduler.pop_due_event(cycle_counter) {
+            match event {
+                Event::Timer => self.update_timer(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cartridge::tests::rom_with_header;
+    use crate::interrupts::Interrupt;
+    use crate::mapper::mbc3::{Mbc3, Rtc};
+
+    #[test]
+    fn echo_ram_mirrors_work_ram() {
+        let mut memory: Memory = Memory::default();
+        memory.set_value_at_memory_address(0xC123, 0x42);
+        assert_eq!(memory.get_value_at_memory_address(0xE123), 0x42);
+        memory.set_value_at_memory_address(0xFDFF, 0x24);
+        assert_eq!(memory.get_value_at_memory_address(0xDDFF), 0x24);
+    }
+
+    #[test]
+    fn unmapped_regions_read_open_bus_values() {
+        let mut memory: Memory = Memory::default();
+        assert_eq!(memory.get_value_at_memory_address(0x0100), 0xFF);
+        assert_eq!(memory.get_value_at_memory_address(0xA000), 0xFF);
+
+        memory.set_value_at_memory_address(0xFEA0, 0x12);
+        assert_eq!(memory.get_value_at_memory_address(0xFEA0), 0x00);
+
+        memory.set_value_at_memory_address(0xFF03, 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0xFF03), 0xFF);
+
+        memory.set_value_at_memory_address(0xFF07, 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0xFF07), 0xF8);
+        memory.set_value_at_memory_address(0xFF41, 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0xFF41), 0x80);
+    }
+
+    #[test]
+    fn rom_writes_are_ignored() {
+        let mut memory: Memory = Memory::default();
+        memory.load_program(0x0150, &[0x3C]);
+        memory.set_value_at_memory_address(0x0150, 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0x0150), 0x3C);
+    }
+
+    #[test]
+    fn cgb_banks_vram_and_wram() {
+        let mut memory: Memory = Memory::new(Model::Cgb);
+        memory.set_value_at_memory_address(0xD000, 0x01);
+        memory.set_value_at_memory_address(0x8000, 0x01);
+
+        memory.set_value_at_memory_address(WRAM_BANK_ADDRESS, 0x03);
+        memory.set_value_at_memory_address(VRAM_BANK_ADDRESS, 0x01);
+        assert_eq!(memory.get_value_at_memory_address(0xD000), 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0x8000), 0x00);
+        memory.set_value_at_memory_address(0xD000, 0x03);
+
+        // Bank 0 selects bank 1
+        memory.set_value_at_memory_address(WRAM_BANK_ADDRESS, 0x00);
+        memory.set_value_at_memory_address(VRAM_BANK_ADDRESS, 0x00);
+        assert_eq!(memory.get_value_at_memory_address(0xD000), 0x01);
+        assert_eq!(memory.get_value_at_memory_address(0x8000), 0x01);
+        assert_eq!(memory.get_value_at_memory_address(WRAM_BANK_ADDRESS), 0xF8);
+    }
+
+    #[test]
+    fn scheduled_timer_matches_a_timer_ticked_every_m_cycle() {
+        let mut memory: Memory = Memory::default();
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        for (memory_address, value) in [
+            (Timer::TAC_ADDRESS, 0b00000101),
+            (Timer::TMA_ADDRESS, 0xFE),
+            (Timer::TIMA_ADDRESS, 0xFC),
+        ] {
+            memory.set_value_at_memory_address(memory_address, value);
+            timer.write_register(memory_address, value);
+        }
+
+        for m_cycle in 0..200 {
+            memory.tick(m_cycle + 1);
+            timer.tick(&mut interrupts);
+            for memory_address in Timer::DIV_ADDRESS..=Timer::TIMA_ADDRESS {
+                assert_eq!(
+                    memory.get_value_at_memory_address(memory_address),
+                    timer.read_register(memory_address)
+                );
+            }
+            assert_eq!(memory.interrupts.interrupt_flag, interrupts.interrupt_flag);
+            if m_cycle % 50 == 0 {
+                memory.set_value_at_memory_address(Timer::DIV_ADDRESS, 0x00);
+                timer.write_register(Timer::DIV_ADDRESS, 0x00);
+            }
+        }
+        assert_eq!(interrupts.interrupt_flag, Interrupt::Timer.bit());
+    }
+
+    #[test]
+    fn fresh_mbc7_eeprom_reads_erased_words() {
+        let mut memory: Memory = Memory::default();
+        let cartridge: Cartridge =
+            Cartridge::new(rom_with_header(b"TILT", 0x22, 0x00, 0x00)).unwrap();
+        memory
+            .load_cartridge(cartridge, ClockSource::Cycles)
+            .unwrap();
+        memory.set_value_at_memory_address(0x0000, 0x0A);
+        memory.set_value_at_memory_address(0x4000, 0x40);
+
+        // Start bit, READ and word 0x00, then a dummy 0 bit and the word clocked out msb first
+        let clock_bit = |memory: &mut Memory, data_in: u8| -> u8 {
+            memory.set_value_at_memory_address(0xA080, 0b10000000 | (data_in << 1));
+            memory.set_value_at_memory_address(0xA080, 0b11000000 | (data_in << 1));
+            memory.get_value_at_memory_address(0xA080) & 1
+        };
+        let read_command: u16 = (1 << 10) | (0b10 << 8);
+        for bit in (0..11).rev() {
+            clock_bit(&mut memory, ((read_command >> bit) & 1) as u8);
+        }
+        let word: u16 = (0..16).fold(0, |word, _| (word << 1) | clock_bit(&mut memory, 0) as u16);
+        assert_eq!(word, 0xFFFF);
+    }
+
+    #[test]
+    fn only_changed_ram_bytes_mark_the_ram_dirty() {
+        let mut memory: Memory = Memory {
+            external_ram: vec![0; 0x8000],
+            mapper: Box::new(Mbc3::new(Some(Rtc::new(ClockSource::Cycles)), false)),
+            ..Memory::default()
+        };
+
+        // RAM disabled
+        memory.set_value_at_memory_address(0xA000, 0x42);
+        assert!(!memory.is_external_ram_dirty);
+
+        // RTC register and latch
+        memory.set_value_at_memory_address(0x0000, 0x0A);
+        memory.set_value_at_memory_address(0x4000, 0x08);
+        memory.set_value_at_memory_address(0xA000, 0x42);
+        memory.set_value_at_memory_address(0x6000, 0x00);
+        memory.set_value_at_memory_address(0x6000, 0x01);
+        assert!(!memory.is_external_ram_dirty);
+
+        // Same value as the one stored
+        memory.set_value_at_memory_address(0x4000, 0x00);
+        memory.set_value_at_memory_address(0xA000, 0x00);
+        assert!(!memory.is_external_ram_dirty);
+        memory.set_value_at_memory_address(0xA000, 0x42);
+        assert!(memory.is_external_ram_dirty);
     }
 }
@@ -0,0 +1,101 @@
+// Post-boot states are based on https://gbdev.io/pandocs/Power_Up_Sequence.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Dmg0,
+    #[default]
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb,
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    // Internal counter of the timer once the boot ROM handed over control, DIV being its upper byte
+    // Only the DMG and MGB lower byte is known, from the mooneye-gb boot_div tests
+    pub fn post_boot_divider(self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x1800,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            // Unknown, Pan Docs leaves these as ?? since the boot time varies: the SGB waits on the
+            // SNES and the CGB on the header it colourises, so the counter just starts from 0
+            Model::Sgb | Model::Sgb2 | Model::Cgb | Model::Agb => 0x0000,
+        }
+    }
+
+    // Values of the I/O registers once the boot ROM handed over control, IF and IE included
+    // DIV is left out as writing it resets the divider
+    pub fn post_boot_io_registers(self) -> Vec<(u16, u8)> {
+        let stat: u8 = if self == Model::Dmg0 { 0x81 } else { 0x85 };
+        let sc: u8 = if self.is_cgb() { 0x7F } else { 0x7E };
+        let dma: u8 = if self.is_cgb() { 0x00 } else { 0xFF };
+        let nr52: u8 = if self.is_sgb() { 0xF0 } else { 0xF1 };
+
+        let mut io_registers: Vec<(u16, u8)> = Vec::from([
+            (0xFF00, 0xCF), // P1
+            (0xFF01, 0x00), // SB
+            (0xFF02, sc),   // SC
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (0xFF0F, 0xE1), // IF
+            (0xFF10, 0x80), // NR10
+            (0xFF11, 0xBF), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF13, 0xFF), // NR13
+            (0xFF14, 0xBF), // NR14
+            (0xFF16, 0x3F), // NR21
+            (0xFF17, 0x00), // NR22
+            (0xFF18, 0xFF), // NR23
+            (0xFF19, 0xBF), // NR24
+            (0xFF1A, 0x7F), // NR30
+            (0xFF1B, 0xFF), // NR31
+            (0xFF1C, 0x9F), // NR32
+            (0xFF1D, 0xFF), // NR33
+            (0xFF1E, 0xBF), // NR34
+            (0xFF20, 0xFF), // NR41
+            (0xFF21, 0x00), // NR42
+            (0xFF22, 0x00), // NR43
+            (0xFF23, 0xBF), // NR44
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF26, nr52), // NR52
+            (0xFF40, 0x91), // LCDC
+            (0xFF41, stat), // STAT
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF44, 0x00), // LY
+            (0xFF45, 0x00), // LYC
+            (0xFF46, dma),  // DMA
+            (0xFF47, 0xFC), // BGP
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+            (0xFFFF, 0x00), // IE
+        ]);
+
+        if self.is_cgb() {
+            io_registers.extend([
+                (0xFF4D, 0x7E), // KEY1
+                (0xFF4F, 0xFE), // VBK
+                (0xFF51, 0xFF), // HDMA1
+                (0xFF52, 0xFF), // HDMA2
+                (0xFF53, 0xFF), // HDMA3
+                (0xFF54, 0xFF), // HDMA4
+                (0xFF55, 0xFF), // HDMA5
+                (0xFF56, 0x3E), // RP
+                (0xFF70, 0xF8), // SVBK
+            ]);
+        }
+
+        io_registers
+    }
+}
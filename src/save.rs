@@ -0,0 +1,376 @@
+// Battery backed cartridge RAM, kept in a .sav file next to the ROM
+// The MBC3 clock footer follows the layout of BGB and VBA-M, see https://bgb.bircd.org/rtcsave.html
+// The HuC3 one follows SameBoy, the only emulator saving that clock
+// The TAMA5 clock has no footer any emulator agrees on, so it is not saved and restarts on each launch
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::EmulatorError;
+use crate::memory::Memory;
+
+pub const RTC_FOOTER_SIZE: usize = 48;
+// Older VBA-M versions write a 32 bit timestamp
+pub const SHORT_RTC_FOOTER_SIZE: usize = 44;
+pub const HUC3_RTC_FOOTER_SIZE: usize = 17;
+
+// Clock state as saved after the cartridge RAM or in a separate .rtc file, all little endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcState {
+    // Each register takes a u32, in the order seconds, minutes, hours, days low and days high,
+    // followed by the Unix time of the save as a u64
+    Mbc3 {
+        registers: [u32; 5],
+        latched_registers: [u32; 5],
+        timestamp: u64,
+    },
+    // The Unix time of the save as a u64, the minute of the day and the day counter as u16, then
+    // the alarm minute and day as u16 and its enable as a u8, the alarm is not emulated
+    HuC3 {
+        minutes: u16,
+        days: u16,
+        timestamp: u64,
+    },
+}
+
+impl RtcState {
+    pub fn to_footer(&self) -> Vec<u8> {
+        let mut footer: Vec<u8> = Vec::with_capacity(RTC_FOOTER_SIZE);
+        match self {
+            RtcState::Mbc3 {
+                registers,
+                latched_registers,
+                timestamp,
+            } => {
+                for register in registers.iter().chain(latched_registers) {
+                    footer.extend(register.to_le_bytes());
+                }
+                footer.extend(timestamp.to_le_bytes());
+            }
+            RtcState::HuC3 {
+                minutes,
+                days,
+                timestamp,
+            } => {
+                footer.extend(timestamp.to_le_bytes());
+                footer.extend(minutes.to_le_bytes());
+                footer.extend(days.to_le_bytes());
+                footer.resize(HUC3_RTC_FOOTER_SIZE, 0x00);
+            }
+        }
+        footer
+    }
+
+    // Tells the layout from the footer size, None for an unknown size
+    pub fn from_footer(footer: &[u8]) -> Option<RtcState> {
+        let get_u16 = |offset: usize| -> u16 {
+            u16::from_le_bytes(footer[offset..offset + 2].try_into().unwrap())
+        };
+        let get_u32 = |offset: usize| -> u32 {
+            u32::from_le_bytes(footer[offset..offset + 4].try_into().unwrap())
+        };
+        let get_u64 = |offset: usize| -> u64 {
+            u64::from_le_bytes(footer[offset..offset + 8].try_into().unwrap())
+        };
+        let timestamp: u64 = match footer.len() {
+            RTC_FOOTER_SIZE => get_u64(40),
+            SHORT_RTC_FOOTER_SIZE => get_u32(40) as u64,
+            HUC3_RTC_FOOTER_SIZE => {
+                return Some(RtcState::HuC3 {
+                    minutes: get_u16(8),
+                    days: get_u16(10),
+                    timestamp: get_u64(0),
+                });
+            }
+            _ => return None,
+        };
+        Some(RtcState::Mbc3 {
+            registers: std::array::from_fn(|index| get_u32(4 * index)),
+            latched_registers: std::array::from_fn(|index| get_u32(20 + 4 * index)),
+            timestamp,
+        })
+    }
+}
+
+// Splits a save into its RAM and its clock footer
+// RAM sizes being multiples of 32 bytes, the footer size is told by the remainder
+pub fn split_rtc_footer(data: &[u8]) -> (&[u8], Option<RtcState>) {
+    let footer_size: usize = match data.len() % 32 {
+        0x10 if data.len() >= RTC_FOOTER_SIZE => RTC_FOOTER_SIZE,
+        0x0C if data.len() >= SHORT_RTC_FOOTER_SIZE => SHORT_RTC_FOOTER_SIZE,
+        0x11 => HUC3_RTC_FOOTER_SIZE,
+        _ => return (data, None),
+    };
+    let (ram, footer): (&[u8], &[u8]) = data.split_at(data.len() - footer_size);
+    (ram, RtcState::from_footer(footer))
+}
+
+pub fn read_file(path: &Path) -> Result<Vec<u8>, EmulatorError> {
+    fs::read(path).map_err(|error| EmulatorError::Io {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    })
+}
+
+#[derive(Debug)]
+pub struct BatterySave {
+    pub path: PathBuf,
+    // M-cycles between two flushes of a dirty RAM, None to only flush on exit
+    pub flush_interval: Option<u64>,
+    pub last_flush_cycle: u64,
+}
+
+impl BatterySave {
+    pub fn new(rom_path: &Path, flush_interval: Option<u64>) -> BatterySave {
+        BatterySave {
+            path: rom_path.with_extension("sav"),
+            flush_interval,
+            last_flush_cycle: 0,
+        }
+    }
+
+    // Fills the cartridge RAM and the clock from the save file, a missing file leaves them cleared
+    pub fn load(&self, memory: &mut Memory) -> Result<(), EmulatorError> {
+        let data: Vec<u8> = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => {
+                return Err(EmulatorError::Io {
+                    path: self.path.display().to_string(),
+                    message: error.to_string(),
+                });
+            }
+        };
+
+        // The footer is optional, a save from a clockless emulator leaves the clock cleared
+        let ram_size: usize = memory.external_ram.len();
+        let rtc_state: Option<RtcState> = match data.len().checked_sub(ram_size) {
+            Some(0) => None,
+            Some(_) => Some(RtcState::from_footer(&data[ram_size..]).ok_or(
+                EmulatorError::InvalidSaveSize {
+                    expected: ram_size + RTC_FOOTER_SIZE,
+                    actual: data.len(),
+                },
+            )?),
+            None => {
+                return Err(EmulatorError::InvalidSaveSize {
+                    expected: ram_size,
+                    actual: data.len(),
+                });
+            }
+        };
+
+        memory.external_ram.copy_from_slice(&data[..ram_size]);
+        memory.is_external_ram_dirty = false;
+        if let Some(rtc_state) = rtc_state {
+            memory.update_mapper();
+            memory.mapper.set_rtc_state(&rtc_state)
+        }
+        Ok(())
+    }
+
+    // Writes the cartridge RAM if it changed since the last flush, a clock is always written
+    // with the time of the flush
+    pub fn flush(&mut self, memory: &mut Memory, cycle_counter: u64) -> Result<(), EmulatorError> {
+        self.last_flush_cycle = cycle_counter;
+        memory.update_mapper();
+        let rtc_state: Option<RtcState> = memory.mapper.get_rtc_state();
+        if !memory.is_external_ram_dirty && rtc_state.is_none() {
+            return Ok(());
+        }
+
+        let mut data: Vec<u8> = memory.external_ram.clone();
+        if let Some(rtc_state) = rtc_state {
+            data.extend(rtc_state.to_footer())
+        }
+        write_atomically(&self.path, &data)?;
+        memory.is_external_ram_dirty = false;
+        Ok(())
+    }
+
+    pub fn flush_if_due(
+        &mut self,
+        memory: &mut Memory,
+        cycle_counter: u64,
+    ) -> Result<(), EmulatorError> {
+        match self.flush_interval {
+            Some(flush_interval)
+                if memory.is_external_ram_dirty
+                    && cycle_counter - self.last_flush_cycle >= flush_interval =>
+            {
+                self.flush(memory, cycle_counter)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Writes to a temporary file renamed over path, so that a crash leaves either the old or the new file
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<(), EmulatorError> {
+    let mut temporary_path: PathBuf = path.to_path_buf().into_os_string().into();
+    temporary_path.as_mut_os_string().push(".tmp");
+
+    let write = || -> io::Result<()> {
+        let mut file: fs::File = fs::File::create(&temporary_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temporary_path, path)?;
+        // Persists the rename itself, directories cannot be opened on every platform
+        if let Some(parent) = path.parent()
+            && let Ok(directory) = fs::File::open(parent)
+        {
+            let _ = directory.sync_all();
+        }
+        Ok(())
+    };
+
+    write().map_err(|error| {
+        let _ = fs::remove_file(&temporary_path);
+        EmulatorError::WriteFailed {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::ClockSource;
+    use crate::mapper::mbc3::{Mbc3, Rtc};
+
+    fn temporary_rom_path(name: &str) -> PathBuf {
+        let directory: PathBuf =
+            std::env::temp_dir().join(format!("rust-boy-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    }
+
+    #[test]
+    fn missing_saves_are_ignored_and_wrong_sizes_rejected() {
+        let rom_path: PathBuf = temporary_rom_path("missing.gb");
+        let battery_save: BatterySave = BatterySave::new(&rom_path, None);
+        let mut memory: Memory = Memory {
+            external_ram: vec![0; 0x2000],
+            ..Memory::default()
+        };
+        assert!(battery_save.load(&mut memory).is_ok());
+
+        fs::write(&battery_save.path, [0x12; 0x800]).unwrap();
+        assert_eq!(
+            battery_save.load(&mut memory).unwrap_err(),
+            EmulatorError::InvalidSaveSize {
+                expected: 0x2000,
+                actual: 0x800
+            }
+        );
+        fs::remove_file(&battery_save.path).unwrap();
+    }
+
+    #[test]
+    fn dirty_ram_is_flushed_on_its_interval() {
+        let rom_path: PathBuf = temporary_rom_path("interval.gbc");
+        let mut battery_save: BatterySave = BatterySave::new(&rom_path, Some(1000));
+        assert_eq!(battery_save.path, rom_path.with_extension("sav"));
+        let mut memory: Memory = Memory {
+            external_ram: vec![0; 0x2000],
+            ..Memory::default()
+        };
+
+        memory.set_value_at_memory_address(0xA000, 0x42);
+        assert!(memory.is_external_ram_dirty);
+        battery_save.flush_if_due(&mut memory, 999).unwrap();
+        assert!(!battery_save.path.exists());
+        battery_save.flush_if_due(&mut memory, 1000).unwrap();
+        assert!(!memory.is_external_ram_dirty);
+
+        let mut restored: Memory = Memory {
+            external_ram: vec![0; 0x2000],
+            ..Memory::default()
+        };
+        battery_save.load(&mut restored).unwrap();
+        assert_eq!(restored.get_value_at_memory_address(0xA000), 0x42);
+        assert!(!rom_path.with_extension("sav.tmp").exists());
+        fs::remove_file(&battery_save.path).unwrap();
+    }
+
+    #[test]
+    fn footers_of_both_sizes_are_split_from_the_ram() {
+        let rtc_state: RtcState = RtcState::Mbc3 {
+            registers: [1, 2, 3, 4, 0xC1],
+            latched_registers: [5, 6, 7, 8, 0x01],
+            timestamp: 0x5F5E1000,
+        };
+        let footer: Vec<u8> = rtc_state.to_footer();
+        assert_eq!(footer.len(), RTC_FOOTER_SIZE);
+        assert_eq!(&footer[16..20], &[0xC1, 0x00, 0x00, 0x00]);
+
+        let mut data: Vec<u8> = vec![0x33; 0x2000];
+        data.extend(&footer[..40]);
+        data.extend(&footer[40..44]);
+        let (ram, short_rtc_state): (&[u8], Option<RtcState>) = split_rtc_footer(&data);
+        assert_eq!(ram.len(), 0x2000);
+        assert_eq!(short_rtc_state, Some(rtc_state));
+
+        let (ram, no_rtc_state): (&[u8], Option<RtcState>) = split_rtc_footer(&data[..0x2000]);
+        assert_eq!(ram.len(), 0x2000);
+        assert_eq!(no_rtc_state, None);
+    }
+
+    #[test]
+    fn huc3_footer_holds_the_timestamp_and_counters() {
+        let rtc_state: RtcState = RtcState::HuC3 {
+            minutes: 0x0123,
+            days: 0x0456,
+            timestamp: 0x5F5E1000,
+        };
+        let footer: Vec<u8> = rtc_state.to_footer();
+        assert_eq!(footer.len(), HUC3_RTC_FOOTER_SIZE);
+        assert_eq!(&footer[8..12], &[0x23, 0x01, 0x56, 0x04]);
+
+        let mut data: Vec<u8> = vec![0x33; 0x8000];
+        data.extend(&footer);
+        let (ram, huc3_rtc_state): (&[u8], Option<RtcState>) = split_rtc_footer(&data);
+        assert_eq!(ram.len(), 0x8000);
+        assert_eq!(huc3_rtc_state, Some(rtc_state));
+    }
+
+    #[test]
+    fn mbc3_clock_is_saved_in_the_footer_and_restored() {
+        let rom_path: PathBuf = temporary_rom_path("clock.gb");
+        let mut battery_save: BatterySave = BatterySave::new(&rom_path, None);
+        let new_memory = || -> Memory {
+            Memory {
+                external_ram: vec![0; 0x8000],
+                mapper: Box::new(Mbc3::new(Some(Rtc::new(ClockSource::Cycles)), false)),
+                ..Memory::default()
+            }
+        };
+
+        let mut memory: Memory = new_memory();
+        let rtc_state: RtcState = RtcState::Mbc3 {
+            registers: [59, 59, 23, 0xFF, 0x01],
+            latched_registers: [10, 20, 3, 0x00, 0x00],
+            timestamp: 0,
+        };
+        memory.mapper.set_rtc_state(&rtc_state);
+        // The clock alone is worth writing on exit
+        battery_save.flush(&mut memory, 0).unwrap();
+        let data: Vec<u8> = fs::read(&battery_save.path).unwrap();
+        assert_eq!(data.len(), 0x8000 + RTC_FOOTER_SIZE);
+
+        let mut restored: Memory = new_memory();
+        battery_save.load(&mut restored).unwrap();
+        // Only the timestamp differs, it is taken when the state is read back
+        let restored_state: Option<RtcState> = restored.mapper.get_rtc_state();
+        assert!(matches!(
+            restored_state,
+            Some(RtcState::Mbc3 {
+                registers: [59, 59, 23, 0xFF, 0x01],
+                latched_registers: [10, 20, 3, 0x00, 0x00],
+                ..
+            })
+        ));
+        fs::remove_file(&battery_save.path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+// Components that run alongside the CPU only need to be brought up to date when they are accessed
+// or when something they do becomes visible, such as an interrupt request
+// Each of them keeps at most one deadline, an absolute value of the CPU M-cycle count
+// The scheduler keeps no time of its own, the CPU count is passed to every query
+// A running CPU still advances the count on every M-cycle, only a halted one skips to the next event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    // The timer overflows or reloads TIMA
+    Timer,
+}
+
+impl Event {
+    pub const ALL: [Event; 1] = [Event::Timer];
+
+    fn index(self) -> usize {
+        match self {
+            Event::Timer => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    pub deadlines: [Option<u64>; Event::ALL.len()],
+    // Earliest deadline, u64::MAX when nothing is scheduled
+    pub next_deadline: u64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            deadlines: [None; Event::ALL.len()],
+            next_deadline: u64::MAX,
+        }
+    }
+}
+
+impl Scheduler {
+    fn update_next_deadline(&mut self) {
+        self.next_deadline = self
+            .deadlines
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    // Replaces the deadline of event, a deadline in the past is due on the next M-cycle
+    pub fn schedule(&mut self, event: Event, cycle: u64) {
+        self.deadlines[event.index()] = Some(cycle);
+        self.update_next_deadline()
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.deadlines[event.index()] = None;
+        self.update_next_deadline()
+    }
+
+    // Whether a deadline was reached at cycle_counter
+    pub fn is_due(&self, cycle_counter: u64) -> bool {
+        cycle_counter >= self.next_deadline
+    }
+
+    // M-cycles left from cycle_counter before the next deadline, None when nothing is scheduled
+    pub fn get_m_cycles_until_next_event(&self, cycle_counter: u64) -> Option<u64> {
+        match self.next_deadline {
+            u64::MAX => None,
+            deadline => Some(deadline.saturating_sub(cycle_counter)),
+        }
+    }
+
+    // Removes and returns an event whose deadline was reached at cycle_counter, earliest first
+    pub fn pop_due_event(&mut self, cycle_counter: u64) -> Option<Event> {
+        if cycle_counter < self.next_deadline {
+            return None;
+        }
+        let event: Event = Event::ALL
+            .into_iter()
+            .find(|event| self.deadlines[event.index()] == Some(self.next_deadline))?;
+        self.cancel(event);
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_due_once_their_deadline_is_reached() {
+        let mut scheduler: Scheduler = Scheduler::default();
+        assert_eq!(scheduler.get_m_cycles_until_next_event(0), None);
+
+        scheduler.schedule(Event::Timer, 10);
+        scheduler.schedule(Event::Timer, 3);
+        assert_eq!(scheduler.get_m_cycles_until_next_event(0), Some(3));
+        assert_eq!(scheduler.get_m_cycles_until_next_event(5), Some(0));
+        assert!(!scheduler.is_due(2));
+        assert_eq!(scheduler.pop_due_event(2), None);
+        assert!(scheduler.is_due(3));
+        assert_eq!(scheduler.pop_due_event(3), Some(Event::Timer));
+        assert_eq!(scheduler.pop_due_event(3), None);
+
+        scheduler.schedule(Event::Timer, 5);
+        scheduler.cancel(Event::Timer);
+        assert!(!scheduler.is_due(100));
+    }
+}
@@ -0,0 +1,300 @@
+// Timer behaviour is based on https://gbdev.io/pandocs/Timer_and_Divider_Registers.html
+// and https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+use crate::interrupts::{Interrupt, InterruptController};
+
+// Where TIMA stands relative to its last overflow
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimaState {
+    #[default]
+    Counting,
+    // TIMA overflowed and reads 0x00 for one M-cycle, a write cancels the reload
+    Overflowed,
+    // TIMA was just reloaded from TMA, writes to TIMA are ignored and writes to TMA go through
+    Reloaded,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Timer {
+    pub divider: u16, // System counter incremented every T-cycle, DIV is its upper byte
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8, // Bit 2 enables TIMA and bits 0-1 select its frequency
+    pub tima_state: TimaState,
+}
+
+impl Timer {
+    pub const DIV_ADDRESS: u16 = 0xFF04;
+    pub const TIMA_ADDRESS: u16 = 0xFF05;
+    pub const TMA_ADDRESS: u16 = 0xFF06;
+    pub const TAC_ADDRESS: u16 = 0xFF07;
+
+    pub fn is_enabled(&self) -> bool {
+        self.tac & 0b00000100 != 0
+    }
+
+    fn get_divider_bit(&self) -> u16 {
+        match self.tac & 0b00000011 {
+            0b00 => 9, // 4096 Hz
+            0b01 => 3, // 262144 Hz
+            0b10 => 5, // 65536 Hz
+            _ => 7,    // 16384 Hz
+        }
+    }
+
+    // TIMA counts the falling edges of this divider bit ANDed with the enable bit, so clearing
+    // either while the bit is set also counts
+    fn get_timer_signal(&self) -> bool {
+        self.is_enabled() && self.divider & (1 << self.get_divider_bit()) != 0
+    }
+
+    fn count_falling_edge(&mut self, previous_signal: bool) {
+        if !previous_signal || self.get_timer_signal() {
+            return;
+        }
+        match self.tima.checked_add(1) {
+            Some(tima) => self.tima = tima,
+            None => {
+                self.tima = 0x00;
+                self.tima_state = TimaState::Overflowed
+            }
+        }
+    }
+
+    // Advances by one M-cycle, the reload and the interrupt come one M-cycle after an overflow
+    pub fn tick(&mut self, interrupts: &mut InterruptController) {
+        match self.tima_state {
+            TimaState::Counting => {}
+            TimaState::Overflowed => {
+                self.tima = self.tma;
+                interrupts.request(Interrupt::Timer);
+                self.tima_state = TimaState::Reloaded
+            }
+            TimaState::Reloaded => self.tima_state = TimaState::Counting,
+        }
+
+        let previous_signal: bool = self.get_timer_signal();
+        self.divider = self.divider.wrapping_add(4);
+        self.count_falling_edge(previous_signal);
+    }
+
+    // M-cycles until TIMA next overflows or reloads, None while it is disabled
+    pub fn get_m_cycles_until_event(&self) -> Option<u64> {
+        if self.tima_state != TimaState::Counting {
+            return Some(1);
+        }
+        if !self.is_enabled() {
+            return None;
+        }
+        // The divider is always a multiple of 4 and so are the edge periods in T-cycles
+        let period: u64 = (1 << (self.get_divider_bit() + 1)) / 4;
+        let until_edge: u64 = period - (self.divider as u64 / 4) % period;
+        Some(until_edge + (0xFF - self.tima as u64) * period)
+    }
+
+    // Advances by m_cycles M-cycles that TIMA does not overflow in
+    fn skip(&mut self, m_cycles: u64) {
+        let divider: u64 = self.divider as u64 + 4 * m_cycles;
+        if self.is_enabled() {
+            let period: u64 = 1 << (self.get_divider_bit() + 1);
+            let edges: u64 = divider / period - self.divider as u64 / period;
+            self.tima += edges as u8;
+        }
+        self.divider = divider as u16;
+    }
+
+    // Same as ticking m_cycles times, with the M-cycles before an overflow skipped at once
+    pub fn advance(&mut self, mut m_cycles: u64, interrupts: &mut InterruptController) {
+        while m_cycles > 0 {
+            let skipped: u64 = match self.get_m_cycles_until_event() {
+                None => m_cycles,
+                Some(until_event) => m_cycles.min(until_event - 1),
+            };
+            if skipped > 0 {
+                self.skip(skipped);
+                m_cycles -= skipped;
+            } else {
+                self.tick(interrupts);
+                m_cycles -= 1;
+            }
+        }
+    }
+
+    // The timer m_cycles M-cycles later, provided no overflow or reload happens in between
+    pub fn get_skipped(&self, m_cycles: u64) -> Timer {
+        let mut timer: Timer = self.clone();
+        timer.skip(m_cycles);
+        timer
+    }
+
+    pub fn read_register(&self, memory_address: u16) -> u8 {
+        match memory_address {
+            Timer::DIV_ADDRESS => (self.divider >> 8) as u8,
+            Timer::TIMA_ADDRESS => self.tima,
+            Timer::TMA_ADDRESS => self.tma,
+            _ => self.tac,
+        }
+    }
+
+    pub fn write_register(&mut self, memory_address: u16, value: u8) {
+        let previous_signal: bool = self.get_timer_signal();
+        match memory_address {
+            // Any write resets the whole divider
+            Timer::DIV_ADDRESS => self.divider = 0,
+            Timer::TIMA_ADDRESS => match self.tima_state {
+                TimaState::Counting => self.tima = value,
+                TimaState::Overflowed => {
+                    self.tima = value;
+                    self.tima_state = TimaState::Counting
+                }
+                TimaState::Reloaded => {}
+            },
+            Timer::TMA_ADDRESS => {
+                self.tma = value;
+                if self.tima_state == TimaState::Reloaded {
+                    self.tima = value
+                }
+            }
+            _ => self.tac = value & 0b00000111,
+        }
+        self.count_falling_edge(previous_signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(timer: &mut Timer, interrupts: &mut InterruptController, m_cycles: u32) {
+        for _ in 0..m_cycles {
+            timer.tick(interrupts)
+        }
+    }
+
+    #[test]
+    fn div_is_the_upper_byte_of_the_divider() {
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        tick(&mut timer, &mut interrupts, 63);
+        assert_eq!(timer.read_register(Timer::DIV_ADDRESS), 0x00);
+        tick(&mut timer, &mut interrupts, 1);
+        assert_eq!(timer.read_register(Timer::DIV_ADDRESS), 0x01);
+
+        timer.write_register(Timer::DIV_ADDRESS, 0x42);
+        assert_eq!(timer.divider, 0x0000);
+    }
+
+    #[test]
+    fn tima_counts_at_the_selected_frequency() {
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000101);
+        tick(&mut timer, &mut interrupts, 4 * 10);
+        assert_eq!(timer.tima, 10);
+
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000100);
+        timer.write_register(Timer::TIMA_ADDRESS, 0x00);
+        tick(&mut timer, &mut interrupts, 256 * 3);
+        assert_eq!(timer.tima, 3);
+
+        // Disabled
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000001);
+        tick(&mut timer, &mut interrupts, 256);
+        assert_eq!(timer.tima, 3);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_and_requests_the_interrupt_one_m_cycle_later() {
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000101);
+        timer.write_register(Timer::TMA_ADDRESS, 0x80);
+        timer.write_register(Timer::TIMA_ADDRESS, 0xFF);
+
+        tick(&mut timer, &mut interrupts, 4);
+        assert_eq!(timer.tima, 0x00);
+        assert_eq!(interrupts.interrupt_flag, 0x00);
+        tick(&mut timer, &mut interrupts, 1);
+        assert_eq!(timer.tima, 0x80);
+        assert_eq!(interrupts.interrupt_flag, Interrupt::Timer.bit());
+    }
+
+    #[test]
+    fn tima_writes_around_the_reload() {
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000101);
+        timer.write_register(Timer::TMA_ADDRESS, 0x80);
+
+        // A write while TIMA reads 0x00 cancels the reload and the interrupt
+        timer.write_register(Timer::TIMA_ADDRESS, 0xFF);
+        tick(&mut timer, &mut interrupts, 4);
+        timer.write_register(Timer::TIMA_ADDRESS, 0x12);
+        tick(&mut timer, &mut interrupts, 1);
+        assert_eq!(timer.tima, 0x12);
+        assert_eq!(interrupts.interrupt_flag, 0x00);
+
+        // A TIMA write during the reload is ignored, a TMA write is copied to TIMA
+        timer.write_register(Timer::TIMA_ADDRESS, 0xFF);
+        tick(&mut timer, &mut interrupts, 3);
+        assert_eq!(timer.tima_state, TimaState::Overflowed);
+        tick(&mut timer, &mut interrupts, 1);
+        timer.write_register(Timer::TIMA_ADDRESS, 0x34);
+        assert_eq!(timer.tima, 0x80);
+        timer.write_register(Timer::TMA_ADDRESS, 0x56);
+        assert_eq!(timer.tima, 0x56);
+        tick(&mut timer, &mut interrupts, 1);
+        assert_eq!(timer.tima_state, TimaState::Counting);
+    }
+
+    #[test]
+    fn advancing_matches_ticking() {
+        let mut ticked: Timer = Timer::default();
+        let mut ticked_interrupts: InterruptController = InterruptController::default();
+        let mut advanced: Timer = Timer::default();
+        let mut advanced_interrupts: InterruptController = InterruptController::default();
+        for timer in [&mut ticked, &mut advanced] {
+            timer.write_register(Timer::TAC_ADDRESS, 0b00000110);
+            timer.write_register(Timer::TMA_ADDRESS, 0xF0);
+            timer.write_register(Timer::TIMA_ADDRESS, 0xF0);
+        }
+
+        for m_cycles in [1, 7, 16, 100, 255, 1000, 3] {
+            tick(&mut ticked, &mut ticked_interrupts, m_cycles);
+            advanced.advance(m_cycles as u64, &mut advanced_interrupts);
+            assert_eq!(advanced.divider, ticked.divider);
+            assert_eq!(advanced.tima, ticked.tima);
+            assert_eq!(advanced.tima_state, ticked.tima_state);
+            assert_eq!(
+                advanced_interrupts.interrupt_flag,
+                ticked_interrupts.interrupt_flag
+            );
+        }
+        assert_eq!(ticked_interrupts.interrupt_flag, Interrupt::Timer.bit());
+    }
+
+    #[test]
+    fn div_and_tac_writes_count_a_falling_edge() {
+        let mut timer: Timer = Timer::default();
+        let mut interrupts: InterruptController = InterruptController::default();
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000101);
+        tick(&mut timer, &mut interrupts, 2);
+        assert_eq!(timer.tima, 0);
+
+        // Bit 3 is set, resetting the divider clears it
+        timer.write_register(Timer::DIV_ADDRESS, 0x00);
+        assert_eq!(timer.tima, 1);
+
+        // Disabling the timer or switching to a cleared bit while the selected bit is set
+        tick(&mut timer, &mut interrupts, 2);
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000001);
+        assert_eq!(timer.tima, 2);
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000101);
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000110);
+        assert_eq!(timer.tima, 3);
+
+        // Nothing happens while the selected bit is clear
+        timer.write_register(Timer::DIV_ADDRESS, 0x00);
+        timer.write_register(Timer::TAC_ADDRESS, 0b00000000);
+        assert_eq!(timer.tima, 3);
+    }
+}